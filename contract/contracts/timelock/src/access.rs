@@ -0,0 +1,139 @@
+//! Admin handover and roles.
+//!
+//! The admin is changed in two steps: the current admin proposes a new
+//! address with `propose_admin`, and that address takes over by calling
+//! `accept_admin`, so a typo cannot hand the contract to a key nobody holds.
+//!
+//! Day-to-day operations are gated by roles the admin grants and revokes.
+//! The admin implicitly holds every role.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env};
+
+use crate::{
+    is_admin, require_admin, storage, AdminProposed, AdminTransferred, DataKey, Error, RoleGranted,
+    RoleRevoked, TimeLockContract, TimeLockContractArgs, TimeLockContractClient,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+#[repr(u32)]
+pub enum Role {
+    Resolver = 0,      // Resolves and cancels markets
+    Treasurer = 1,     // Withdraws from the treasury
+    Pauser = 2,        // Pauses and unpauses operations
+    ConfigManager = 3, // Changes the protocol parameters
+}
+
+pub(crate) fn has_role(env: &Env, role: Role, account: &Address) -> Result<bool, Error> {
+    Ok(is_admin(env, account)? || storage::has_role(env, role, account))
+}
+
+pub(crate) fn require_role(env: &Env, role: Role, account: &Address) -> Result<(), Error> {
+    if !has_role(env, role, account)? {
+        return Err(Error::Unauthorized);
+    }
+    Ok(())
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Propose a new admin, who must call `accept_admin` (admin only).
+    /// Proposing again replaces the pending proposal.
+    pub fn propose_admin(env: Env, admin: Address, new_admin: Address) -> Result<(), Error> {
+        admin.require_auth();
+        storage::extend_instance(&env);
+        require_admin(&env, &admin)?;
+
+        storage::set_pending_admin(&env, &new_admin);
+
+        AdminProposed { admin, new_admin }.publish(&env);
+
+        Ok(())
+    }
+
+    /// Take over as admin after being proposed
+    pub fn accept_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        new_admin.require_auth();
+        storage::extend_instance(&env);
+
+        let pending = storage::get_pending_admin(&env).ok_or(Error::NoPendingAdmin)?;
+        if new_admin != pending {
+            return Err(Error::Unauthorized);
+        }
+
+        let previous_admin: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(Error::NotInitialized)?;
+        env.storage().instance().set(&DataKey::Admin, &new_admin);
+        storage::remove_pending_admin(&env);
+
+        AdminTransferred {
+            previous_admin,
+            new_admin,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Grant a role to an account (admin only)
+    pub fn grant_role(env: Env, admin: Address, role: Role, account: Address) -> Result<(), Error> {
+        admin.require_auth();
+        storage::extend_instance(&env);
+        require_admin(&env, &admin)?;
+
+        storage::set_role(&env, role, &account, true);
+
+        RoleGranted {
+            role,
+            account,
+            admin,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Revoke a role from an account (admin only)
+    pub fn revoke_role(
+        env: Env,
+        admin: Address,
+        role: Role,
+        account: Address,
+    ) -> Result<(), Error> {
+        admin.require_auth();
+        storage::extend_instance(&env);
+        require_admin(&env, &admin)?;
+
+        storage::set_role(&env, role, &account, false);
+
+        RoleRevoked {
+            role,
+            account,
+            admin,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Check whether an account holds a role, directly or as the admin
+    pub fn has_role(env: Env, role: Role, account: Address) -> Result<bool, Error> {
+        has_role(&env, role, &account)
+    }
+
+    /// Get the current admin
+    pub fn get_admin(env: Env) -> Result<Address, Error> {
+        env.storage()
+            .instance()
+            .get(&DataKey::Admin)
+            .ok_or(Error::NotInitialized)
+    }
+
+    /// Get the admin proposed by `propose_admin`, if any
+    pub fn get_pending_admin(env: Env) -> Option<Address> {
+        storage::get_pending_admin(&env)
+    }
+}
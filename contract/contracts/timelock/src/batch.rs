@@ -0,0 +1,131 @@
+//! Batch claims.
+//!
+//! `claim_many` settles a list of predictions of any type under a single
+//! authorization. Each id goes through the same checks and payout as the
+//! claim or refund entry point for its market type: winnings once resolved,
+//! the full stake (or for LMSR and CPMM, the shares at their final prices)
+//! once cancelled or expired. CPMM liquidity is withdrawn separately. An id that
+//! cannot be claimed is reported and skipped instead of failing the whole
+//! batch.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env, Map, Vec};
+
+use crate::{
+    is_refundable, load_claim, refund_outcome_stake, refund_scalar_stake, refund_stake,
+    require_not_paused, settle_claim, settle_cpmm_claim, settle_lmsr_claim, settle_outcome_claim,
+    settle_scalar_claim, storage, Error, MarketKind, Operation, TimeLockContract,
+    TimeLockContractArgs, TimeLockContractClient,
+};
+
+pub const MAX_BATCH_SIZE: u32 = 25;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ClaimResult {
+    pub prediction_id: u64,
+    pub payout: i128,
+    pub reason: u32, // Code of the `Error` that skipped this claim, or 0
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct BatchClaim {
+    pub results: Vec<ClaimResult>,  // One per requested id, in order
+    pub totals: Map<Address, i128>, // Amount transferred per token
+}
+
+/// Claim or refund one prediction of any type, returning the token paid out
+/// and the amount.
+fn claim_one(env: &Env, user: &Address, prediction_id: u64) -> Result<(Address, i128), Error> {
+    let kind = storage::get_market_kind(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+    match kind {
+        MarketKind::Binary => {
+            let prediction =
+                storage::get_prediction(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+            if is_refundable(&prediction.status) {
+                let amount = refund_stake(env, &prediction, user)?;
+                return Ok((prediction.token, amount));
+            }
+            let (prediction, stake, payout) = load_claim(env, prediction_id, user)?;
+            let token = prediction.token.clone();
+            settle_claim(env, user, prediction, stake, payout);
+            Ok((token, payout))
+        }
+        MarketKind::Categorical => {
+            let prediction =
+                storage::get_categorical(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+            let token = prediction.token.clone();
+            let amount = if is_refundable(&prediction.status) {
+                refund_outcome_stake(env, &prediction, user)?
+            } else {
+                settle_outcome_claim(env, prediction, user)?
+            };
+            Ok((token, amount))
+        }
+        MarketKind::Scalar => {
+            let prediction =
+                storage::get_scalar(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+            let token = prediction.token.clone();
+            let amount = if is_refundable(&prediction.status) {
+                refund_scalar_stake(env, &prediction, user)?
+            } else {
+                settle_scalar_claim(env, prediction, user)?
+            };
+            Ok((token, amount))
+        }
+        MarketKind::Lmsr => {
+            let prediction =
+                storage::get_lmsr(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+            let amount = settle_lmsr_claim(env, &prediction, user)?;
+            Ok((prediction.token, amount))
+        }
+        MarketKind::Cpmm => {
+            let prediction =
+                storage::get_cpmm(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+            let amount = settle_cpmm_claim(env, &prediction, user)?;
+            Ok((prediction.token, amount))
+        }
+    }
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Claim winnings or refunds from up to `MAX_BATCH_SIZE` predictions of
+    /// any type at once. Each payout is made in its prediction's own token.
+    pub fn claim_many(
+        env: Env,
+        user: Address,
+        prediction_ids: Vec<u64>,
+    ) -> Result<BatchClaim, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        if prediction_ids.len() > MAX_BATCH_SIZE {
+            return Err(Error::BatchTooLarge);
+        }
+
+        let mut results = Vec::new(&env);
+        let mut totals = Map::new(&env);
+        for prediction_id in prediction_ids.iter() {
+            let result = match claim_one(&env, &user, prediction_id) {
+                Ok((token, payout)) => {
+                    totals.set(token.clone(), totals.get(token).unwrap_or(0) + payout);
+                    ClaimResult {
+                        prediction_id,
+                        payout,
+                        reason: 0,
+                    }
+                }
+                Err(error) => ClaimResult {
+                    prediction_id,
+                    payout: 0,
+                    reason: error as u32,
+                },
+            };
+            results.push_back(result);
+        }
+
+        Ok(BatchClaim { results, totals })
+    }
+}
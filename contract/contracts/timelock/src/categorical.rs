@@ -0,0 +1,516 @@
+//! Categorical (multi-outcome) predictions.
+//!
+//! A categorical prediction has 2 to `MAX_OUTCOMES` named outcomes, one pool
+//! per outcome, and resolves to a single outcome index. Fees, minimum stakes
+//! and the pro-rata payout from the combined pool work exactly like the
+//! binary `Prediction`. Ids are shared with binary predictions. Unlike
+//! binary predictions, staking closes and resolution opens at a single
+//! `unlock_time`, with no `lock` step.
+//!
+//! If nobody staked on the winning outcome, or only the winning outcome has
+//! stakes, there is no one to pay or no one to pay from, so every staker
+//! claims back their own stake instead and no fee is taken. The same happens
+//! when a resolver resolves the prediction as invalid.
+//!
+//! Unresolved predictions can be cancelled with `cancel_outcome` on the same
+//! terms as binary ones, or expired with `expire_outcome` once their
+//! resolution deadline passes, after which stakes are returned by
+//! `refund_outcome`.
+
+use soroban_sdk::{contractimpl, contracttype, token, Address, Env, String, Vec};
+
+use crate::{
+    extended_deadline, has_role, next_prediction_id, paused_total, pay_refund, require_not_paused,
+    require_refundable, require_role, require_unsettled, storage, take_creation_payment,
+    validate_market, CategoricalCreated, Claimed, Error, MarketKind, Operation, OutcomeCancelled,
+    OutcomeExpired, OutcomeResolved, OutcomeResolvedInvalid, OutcomeStaked, PredictionStatus, Role,
+    Settlement, TimeLockContract, TimeLockContractArgs, TimeLockContractClient,
+};
+
+const MAX_OUTCOMES: u32 = 16;
+const MAX_OUTCOME_LEN: u32 = 64;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CategoricalPrediction {
+    pub id: u64,
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64, // Expires if still unresolved by then, see `extended_deadline`
+    pub pause_offset: u64,     // Resolve pause time before creation
+    pub outcomes: Vec<String>,
+    pub pools: Vec<i128>, // One pool per outcome, same order as `outcomes`
+    pub status: PredictionStatus,
+    pub winner: Option<u32>, // Index of the winning outcome, None if resolved as invalid
+    pub settlement: Settlement,
+}
+
+impl CategoricalPrediction {
+    fn total_pool(&self) -> i128 {
+        self.pools.iter().sum()
+    }
+
+    /// What `claim_outcome` pays out for `stake`, or why it cannot be claimed.
+    pub(crate) fn payout_for(&self, stake: &OutcomeStake) -> Result<i128, Error> {
+        match self.status {
+            PredictionStatus::Cancelled => return Err(Error::PredictionCancelled),
+            PredictionStatus::Expired => return Err(Error::ResolutionExpired),
+            PredictionStatus::Resolved => {}
+            _ => return Err(Error::NotResolved),
+        }
+
+        if stake.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        // When every stake is being returned, losers are eligible too
+        let refunding = self.settlement.eligible_stake == self.total_pool();
+        if !refunding && self.winner != Some(stake.outcome) {
+            return Err(Error::LostPrediction);
+        }
+
+        Ok(self.settlement.payout(stake.amount))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct OutcomeStake {
+    pub user: Address,
+    pub outcome: u32,
+    pub amount: i128,
+    pub claimed: bool,
+}
+
+/// Load a categorical prediction and check it can be resolved now.
+fn load_resolvable(env: &Env, prediction_id: u64) -> Result<CategoricalPrediction, Error> {
+    let prediction =
+        storage::get_categorical(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+    require_unsettled(&prediction.status)?;
+
+    let current_time = env.ledger().timestamp();
+    if current_time < prediction.unlock_time {
+        return Err(Error::ResolveTooEarly);
+    }
+    if current_time >= extended_deadline(env, prediction.resolve_deadline, prediction.pause_offset)
+    {
+        return Err(Error::ResolutionExpired);
+    }
+
+    Ok(prediction)
+}
+
+/// Pay out a user's claim on a resolved categorical prediction.
+pub(crate) fn settle_outcome_claim(
+    env: &Env,
+    mut prediction: CategoricalPrediction,
+    user: &Address,
+) -> Result<i128, Error> {
+    let mut stake =
+        storage::get_outcome_stake(env, prediction.id, user).ok_or(Error::StakeNotFound)?;
+
+    let payout = prediction.payout_for(&stake)?;
+    prediction
+        .settlement
+        .record_claim(env, &prediction.token, stake.amount, payout);
+    storage::set_categorical(env, &prediction);
+
+    stake.claimed = true;
+    storage::set_outcome_stake(env, prediction.id, &stake);
+
+    let token_client = token::Client::new(env, &prediction.token);
+    token_client.transfer(&env.current_contract_address(), user, &payout);
+
+    Claimed {
+        prediction_id: prediction.id,
+        user: user.clone(),
+        stake: stake.amount,
+        payout,
+        platform_fee: prediction.settlement.platform_fee,
+    }
+    .publish(env);
+
+    Ok(payout)
+}
+
+/// Return a user's stake in full from a cancelled or expired categorical
+/// prediction.
+pub(crate) fn refund_outcome_stake(
+    env: &Env,
+    prediction: &CategoricalPrediction,
+    user: &Address,
+) -> Result<i128, Error> {
+    let mut stake =
+        storage::get_outcome_stake(env, prediction.id, user).ok_or(Error::StakeNotFound)?;
+    if stake.claimed {
+        return Err(Error::AlreadyClaimed);
+    }
+
+    stake.claimed = true;
+    storage::set_outcome_stake(env, prediction.id, &stake);
+
+    pay_refund(
+        env,
+        prediction.id,
+        user.clone(),
+        &prediction.token,
+        stake.amount,
+    );
+
+    Ok(stake.amount)
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Create a prediction with 2 or more named outcomes
+    pub fn create_categorical(
+        env: Env,
+        creator: Address,
+        question: String,
+        outcomes: Vec<String>,
+        unlock_time: u64,
+        initial_outcome: u32,
+        token: Address,
+    ) -> Result<u64, Error> {
+        creator.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Create)?;
+
+        // Validate inputs
+        let config = storage::get_config(&env);
+        validate_market(&env, &config, &question, unlock_time)?;
+
+        if outcomes.len() < 2 || outcomes.len() > MAX_OUTCOMES {
+            return Err(Error::InvalidOutcomes);
+        }
+        for outcome in outcomes.iter() {
+            if outcome.is_empty() || outcome.len() > MAX_OUTCOME_LEN {
+                return Err(Error::InvalidOutcomes);
+            }
+        }
+
+        if initial_outcome >= outcomes.len() {
+            return Err(Error::InvalidOutcome);
+        }
+
+        let count = next_prediction_id(&env);
+        take_creation_payment(&env, &config, &creator, &token);
+
+        // Create prediction with the creator's stake in its initial outcome
+        let mut pools = Vec::new(&env);
+        for i in 0..outcomes.len() {
+            pools.push_back(if i == initial_outcome {
+                config.min_stake
+            } else {
+                0
+            });
+        }
+        let prediction = CategoricalPrediction {
+            id: count,
+            creator: creator.clone(),
+            question,
+            token,
+            unlock_time,
+            resolve_deadline: unlock_time + config.resolution_window,
+            pause_offset: paused_total(&env, Operation::Resolve),
+            outcomes,
+            pools,
+            status: PredictionStatus::Open,
+            winner: None,
+            settlement: Settlement::default(),
+        };
+        storage::set_categorical(&env, &prediction);
+
+        // Store creator's stake
+        let stake = OutcomeStake {
+            user: creator,
+            outcome: initial_outcome,
+            amount: config.min_stake,
+            claimed: false,
+        };
+        storage::set_outcome_stake(&env, count, &stake);
+        storage::add_user_position(&env, &stake.user, count, MarketKind::Categorical);
+
+        CategoricalCreated {
+            prediction_id: count,
+            creator: stake.user,
+            question: prediction.question,
+            token: prediction.token,
+            unlock_time: prediction.unlock_time,
+            resolve_deadline: prediction.resolve_deadline,
+            outcomes: prediction.outcomes,
+            outcome: initial_outcome,
+            amount: config.min_stake,
+            creation_fee: config.creation_fee,
+        }
+        .publish(&env);
+
+        Ok(count)
+    }
+
+    /// Stake on one outcome of a categorical prediction
+    pub fn stake_outcome(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        outcome_index: u32,
+        amount: i128,
+        token: Address,
+    ) -> Result<(), Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        // Check if user already staked
+        if storage::get_outcome_stake(&env, prediction_id, &user).is_some() {
+            return Err(Error::AlreadyStaked);
+        }
+
+        // Validate amount
+        if amount < storage::get_config(&env).min_stake {
+            return Err(Error::StakeTooLow);
+        }
+
+        let mut prediction =
+            storage::get_categorical(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        match prediction.status {
+            PredictionStatus::Open => {}
+            _ => return Err(Error::PredictionNotOpen),
+        }
+
+        if env.ledger().timestamp() >= prediction.unlock_time {
+            return Err(Error::PredictionExpired);
+        }
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        let pool = prediction
+            .pools
+            .get(outcome_index)
+            .ok_or(Error::InvalidOutcome)?;
+
+        // Transfer tokens
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&user, env.current_contract_address(), &amount);
+
+        prediction.pools.set(outcome_index, pool + amount);
+        storage::set_categorical(&env, &prediction);
+
+        let stake = OutcomeStake {
+            user: user.clone(),
+            outcome: outcome_index,
+            amount,
+            claimed: false,
+        };
+        storage::set_outcome_stake(&env, prediction_id, &stake);
+        storage::add_user_position(&env, &stake.user, prediction_id, MarketKind::Categorical);
+
+        OutcomeStaked {
+            prediction_id,
+            user,
+            outcome: outcome_index,
+            amount,
+            pools: prediction.pools,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Resolve a categorical prediction to one outcome (resolver only)
+    pub fn resolve_outcome(
+        env: Env,
+        resolver: Address,
+        prediction_id: u64,
+        outcome_index: u32,
+    ) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
+
+        let mut prediction = load_resolvable(&env, prediction_id)?;
+
+        if outcome_index >= prediction.outcomes.len() {
+            return Err(Error::InvalidOutcome);
+        }
+
+        let total_pool = prediction.total_pool();
+        let winning_pool = prediction.pools.get_unchecked(outcome_index);
+
+        prediction.status = PredictionStatus::Resolved;
+        prediction.winner = Some(outcome_index);
+        prediction.settlement = if winning_pool == 0 || winning_pool == total_pool {
+            // Nobody to pay or nobody to pay from: every stake is returned
+            Settlement::refund(total_pool)
+        } else {
+            Settlement::winners(&env, &prediction.token, total_pool, winning_pool)
+        };
+        storage::set_categorical(&env, &prediction);
+
+        OutcomeResolved {
+            prediction_id,
+            resolver,
+            outcome: outcome_index,
+            pools: prediction.pools,
+            platform_fee: prediction.settlement.platform_fee,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Resolve a categorical prediction as invalid when the question cannot
+    /// be answered (resolver only). Every staker can then claim back their
+    /// own stake with `claim_outcome`.
+    pub fn resolve_outcome_invalid(
+        env: Env,
+        resolver: Address,
+        prediction_id: u64,
+    ) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
+
+        let mut prediction = load_resolvable(&env, prediction_id)?;
+
+        prediction.status = PredictionStatus::Resolved;
+        prediction.settlement = Settlement::refund(prediction.total_pool());
+        storage::set_categorical(&env, &prediction);
+
+        OutcomeResolvedInvalid {
+            prediction_id,
+            resolver,
+            pools: prediction.pools,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Claim winnings from a categorical prediction
+    pub fn claim_outcome(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let prediction =
+            storage::get_categorical(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        settle_outcome_claim(&env, prediction, &user)
+    }
+
+    /// Cancel an unresolved categorical prediction so every staker can take
+    /// their stake back with `refund_outcome`. A resolver can cancel any
+    /// unresolved prediction; the creator can cancel only while nobody else
+    /// has staked. The creation fee is not refunded.
+    pub fn cancel_outcome(env: Env, caller: Address, prediction_id: u64) -> Result<(), Error> {
+        caller.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_categorical(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        if !has_role(&env, Role::Resolver, &caller)? {
+            if caller != prediction.creator {
+                return Err(Error::Unauthorized);
+            }
+            // Only the creator's initial stake may be in the pools
+            let creator_stake = storage::get_outcome_stake(&env, prediction_id, &caller)
+                .map_or(0, |stake| stake.amount);
+            if prediction.total_pool() != creator_stake {
+                return Err(Error::CannotCancel);
+            }
+        }
+
+        prediction.status = PredictionStatus::Cancelled;
+        storage::set_categorical(&env, &prediction);
+
+        OutcomeCancelled {
+            prediction_id,
+            caller,
+            pools: prediction.pools,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Mark a categorical prediction that was not resolved by its deadline
+    /// as expired so every staker can take their stake back with
+    /// `refund_outcome`. Anyone can call this.
+    pub fn expire_outcome(env: Env, prediction_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_categorical(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        let deadline =
+            extended_deadline(&env, prediction.resolve_deadline, prediction.pause_offset);
+        if env.ledger().timestamp() < deadline {
+            return Err(Error::DeadlineNotReached);
+        }
+
+        prediction.status = PredictionStatus::Expired;
+        storage::set_categorical(&env, &prediction);
+
+        OutcomeExpired {
+            prediction_id,
+            pools: prediction.pools,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Take back the full stake from a cancelled or expired categorical
+    /// prediction
+    pub fn refund_outcome(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let prediction =
+            storage::get_categorical(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_refundable(&prediction.status)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        refund_outcome_stake(&env, &prediction, &user)
+    }
+
+    /// Get categorical prediction details
+    pub fn get_categorical(env: Env, prediction_id: u64) -> Result<CategoricalPrediction, Error> {
+        storage::get_categorical(&env, prediction_id).ok_or(Error::PredictionNotFound)
+    }
+
+    /// Get user stake on a categorical prediction
+    pub fn get_outcome_stake(env: Env, prediction_id: u64, user: Address) -> Option<OutcomeStake> {
+        storage::get_outcome_stake(&env, prediction_id, &user)
+    }
+}
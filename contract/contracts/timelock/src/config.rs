@@ -0,0 +1,126 @@
+//! Protocol parameters.
+//!
+//! Fees, the minimum stake, the minimum time before a market unlocks, how
+//! long a resolver has to resolve it and the penalty for leaving a market
+//! early live in a single `Config` stored in instance storage. `initialize` stores the
+//! defaults below and the config manager can change them with `set_config`. Changes
+//! apply to future actions only: fees already settled on a resolved market
+//! are never recomputed.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env};
+
+use crate::{
+    require_role, storage, ConfigUpdated, Error, Role, TimeLockContract, TimeLockContractArgs,
+    TimeLockContractClient,
+};
+
+pub const DEFAULT_CREATION_FEE: i128 = 50_0000000; // 50 XLM (7 decimals)
+pub const DEFAULT_MIN_STAKE: i128 = 100_0000000; // 100 XLM
+pub const DEFAULT_MIN_LEAD_TIME: u64 = 3600; // 1 hour in seconds
+pub const DEFAULT_PLATFORM_FEE_BPS: u32 = 500; // 5% of each resolved pool
+pub const DEFAULT_TREASURY_SHARE_BPS: u32 = 7000; // 70% of the creation fee, the rest is burned
+pub const DEFAULT_RESOLUTION_WINDOW: u64 = 14 * 24 * 3600; // 14 days in seconds
+pub const DEFAULT_EXIT_PENALTY_BPS: u32 = 500; // 5% of a withdrawn stake
+pub const DEFAULT_PENALTY_TREASURY_SHARE_BPS: u32 = 5000; // 50% of the penalty, the rest stays in the pool
+
+pub(crate) const BPS_DENOMINATOR: i128 = 10_000;
+
+// Bounds enforced by `set_config`
+const MAX_PLATFORM_FEE_BPS: u32 = 1000; // 10%
+const MAX_MIN_LEAD_TIME: u64 = 30 * 24 * 3600; // 30 days
+const MIN_RESOLUTION_WINDOW: u64 = 24 * 3600; // 1 day
+const MAX_RESOLUTION_WINDOW: u64 = 365 * 24 * 3600; // 1 year
+const MAX_EXIT_PENALTY_BPS: u32 = 5000; // 50%
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Config {
+    pub creation_fee: i128,
+    pub min_stake: i128,
+    pub min_lead_time: u64, // Seconds between creation and the earliest unlock time
+    pub platform_fee_bps: u32,
+    pub treasury_share_bps: u32, // Share of the creation fee credited to the treasury
+    pub resolution_window: u64,  // Seconds after `resolve_after` before a market can expire
+    pub exit_penalty_bps: u32,   // Share of a stake kept when it is withdrawn early
+    pub penalty_treasury_share_bps: u32, // Share of that penalty credited to the treasury
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            creation_fee: DEFAULT_CREATION_FEE,
+            min_stake: DEFAULT_MIN_STAKE,
+            min_lead_time: DEFAULT_MIN_LEAD_TIME,
+            platform_fee_bps: DEFAULT_PLATFORM_FEE_BPS,
+            treasury_share_bps: DEFAULT_TREASURY_SHARE_BPS,
+            resolution_window: DEFAULT_RESOLUTION_WINDOW,
+            exit_penalty_bps: DEFAULT_EXIT_PENALTY_BPS,
+            penalty_treasury_share_bps: DEFAULT_PENALTY_TREASURY_SHARE_BPS,
+        }
+    }
+}
+
+impl Config {
+    fn validate(&self) -> Result<(), Error> {
+        if self.creation_fee < 0
+            || self.min_stake <= 0
+            || self.min_lead_time > MAX_MIN_LEAD_TIME
+            || self.platform_fee_bps > MAX_PLATFORM_FEE_BPS
+            || self.treasury_share_bps as i128 > BPS_DENOMINATOR
+            || self.resolution_window < MIN_RESOLUTION_WINDOW
+            || self.resolution_window > MAX_RESOLUTION_WINDOW
+            || self.exit_penalty_bps > MAX_EXIT_PENALTY_BPS
+            || self.penalty_treasury_share_bps as i128 > BPS_DENOMINATOR
+        {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(())
+    }
+
+    /// Split a pool into (prize pool, platform fee). The fee rounds up.
+    pub(crate) fn split_pool(&self, total_pool: i128) -> (i128, i128) {
+        let prize_pool =
+            total_pool * (BPS_DENOMINATOR - self.platform_fee_bps as i128) / BPS_DENOMINATOR;
+        (prize_pool, total_pool - prize_pool)
+    }
+
+    /// Split the penalty on withdrawing `amount` into (penalty, treasury
+    /// share). The penalty rounds up.
+    pub(crate) fn exit_penalty(&self, amount: i128) -> (i128, i128) {
+        let returned = amount * (BPS_DENOMINATOR - self.exit_penalty_bps as i128) / BPS_DENOMINATOR;
+        let penalty = amount - returned;
+        let to_treasury = penalty * self.penalty_treasury_share_bps as i128 / BPS_DENOMINATOR;
+        (penalty, to_treasury)
+    }
+
+    /// The part of the creation fee credited to the treasury.
+    pub(crate) fn treasury_share(&self) -> i128 {
+        self.creation_fee * self.treasury_share_bps as i128 / BPS_DENOMINATOR
+    }
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Replace the protocol parameters (config manager only)
+    pub fn set_config(env: Env, config_manager: Address, config: Config) -> Result<(), Error> {
+        config_manager.require_auth();
+        storage::extend_instance(&env);
+        require_role(&env, Role::ConfigManager, &config_manager)?;
+
+        config.validate()?;
+        storage::set_config(&env, &config);
+
+        ConfigUpdated {
+            config_manager,
+            config,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Get the current protocol parameters
+    pub fn get_config(env: Env) -> Config {
+        storage::get_config(&env)
+    }
+}
@@ -0,0 +1,840 @@
+//! Constant-product (CPMM) predictions.
+//!
+//! A CPMM prediction is a pool of YES and NO outcome tokens. Collateral is
+//! always split into complete sets, one YES plus one NO token per base unit,
+//! so the outcome tokens in existence are always backed 1:1. On resolution
+//! each winning token redeems for one base unit and losing tokens are
+//! worthless.
+//!
+//! Buying YES with collateral splits it into sets, adds them to the pool and
+//! takes out as many YES tokens as keeps `yes_reserve * no_reserve` constant.
+//! Selling YES puts the tokens into the pool and takes out equal amounts of
+//! YES and NO, which merge back into collateral. The price of YES is
+//! `no_reserve / (yes_reserve + no_reserve)`.
+//!
+//! The creator seeds the pool and anyone can add liquidity while it is open.
+//! Each trade pays `fee_bps` of its collateral to the liquidity providers.
+//! Liquidity is withdrawn after resolution as the provider's share of the
+//! winning reserve plus their share of the fees. Fees are tracked with a
+//! virtual total so providers who join later do not take fees paid before
+//! they joined.
+//!
+//! A market that is cancelled or expires instead of being resolved settles
+//! at its final prices: each YES token redeems for the YES price and each NO
+//! token for the NO price, so a complete set is still worth one base unit,
+//! and providers withdraw their share of the reserves at the same prices.
+//! Once resolution has been paused for `EMERGENCY_DELAY`, anyone can cancel
+//! a CPMM prediction that can no longer be traded, because trading has
+//! closed or is paused, which opens both exits.
+//!
+//! Rounding always favours the pool.
+
+use soroban_sdk::{contractimpl, contracttype, token, Address, Env, String};
+
+use crate::config::BPS_DENOMINATOR;
+use crate::fixed::{sqrt, ONE};
+use crate::{
+    add_to_treasury, emergency_open, extended_deadline, has_role, next_prediction_id, paused_total,
+    require_not_paused, require_role, require_trading, require_unsettled, storage, validate_market,
+    CpmmCancelled, CpmmCreated, CpmmExpired, CpmmResolved, Error, LiquidityAdded,
+    LiquidityWithdrawn, MarketKind, Operation, PredictionStatus, Resolution, Role, SharesBought,
+    SharesRedeemed, SharesSold, TimeLockContract, TimeLockContractArgs, TimeLockContractClient,
+};
+
+const MAX_FEE_BPS: u32 = 1000; // 10%
+
+// Keeps the sell quadratic inside i128
+const MAX_SETS: i128 = 1_000_000_000_000_000_000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CpmmPrediction {
+    pub id: u64,
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64,
+    pub pause_offset: u64, // Resolve pause time before creation
+    pub fee_bps: u32,
+    pub yes_reserve: i128,
+    pub no_reserve: i128,
+    pub sets: i128, // Complete sets in existence, each backed by one base unit
+    pub lp_supply: i128,
+    pub fees: i128, // Virtual fee total shared by `lp_supply`
+    pub status: PredictionStatus,
+    pub winner: Resolution,
+    pub void_price: i128, // YES price a cancelled or expired market settles at
+}
+
+impl CpmmPrediction {
+    /// The fee on `amount` of collateral, rounded up.
+    fn fee(&self, amount: i128) -> i128 {
+        (amount * self.fee_bps as i128 + BPS_DENOMINATOR - 1) / BPS_DENOMINATOR
+    }
+
+    /// Apply a buy of `outcome` with `amount` of collateral. Returns the
+    /// tokens bought and the fee.
+    fn buy(&mut self, outcome: bool, amount: i128) -> Result<(i128, i128), Error> {
+        let fee = self.fee(amount);
+        let invested = amount - fee;
+        if self.sets + invested > MAX_SETS {
+            return Err(Error::InvalidLiquidity);
+        }
+
+        let product = self.yes_reserve * self.no_reserve;
+        let (bought, other) = if outcome {
+            (self.yes_reserve + invested, self.no_reserve + invested)
+        } else {
+            (self.no_reserve + invested, self.yes_reserve + invested)
+        };
+        // Round the reserve left in the pool up
+        let remaining = (product + other - 1) / other;
+        let tokens = bought - remaining;
+
+        if outcome {
+            (self.yes_reserve, self.no_reserve) = (remaining, other);
+        } else {
+            (self.yes_reserve, self.no_reserve) = (other, remaining);
+        }
+        self.sets += invested;
+        self.fees += fee;
+        Ok((tokens, fee))
+    }
+
+    /// Apply a sale of `tokens` of `outcome`. Returns the collateral paid out
+    /// after the fee.
+    fn sell(&mut self, outcome: bool, tokens: i128) -> i128 {
+        let (sold, other) = if outcome {
+            (self.yes_reserve + tokens, self.no_reserve)
+        } else {
+            (self.no_reserve + tokens, self.yes_reserve)
+        };
+
+        // Largest `merged` with (sold - merged) * (other - merged) >= product,
+        // the smaller root of the quadratic with the square root rounded up
+        let product = self.yes_reserve * self.no_reserve;
+        let sum = sold + other;
+        let discriminant = sum * sum - 4 * (sold * other - product);
+        let mut root = sqrt(discriminant);
+        if root * root < discriminant {
+            root += 1;
+        }
+        let merged = (sum - root) / 2;
+
+        if outcome {
+            (self.yes_reserve, self.no_reserve) = (sold - merged, other - merged);
+        } else {
+            (self.yes_reserve, self.no_reserve) = (other - merged, sold - merged);
+        }
+        self.sets -= merged;
+
+        let fee = self.fee(merged);
+        self.fees += fee;
+        merged - fee
+    }
+
+    /// The current (YES, NO) prices scaled by `ONE`. They always sum to `ONE`,
+    /// and are fixed at the settlement prices once the market is settled.
+    fn prices(&self) -> (i128, i128) {
+        if let Some(prices) = self.settlement_prices() {
+            return prices;
+        }
+        let yes_price = self.no_reserve * ONE / (self.yes_reserve + self.no_reserve);
+        (yes_price, ONE - yes_price)
+    }
+
+    /// The (YES, NO) prices tokens redeem at, scaled by `ONE`: the winner
+    /// takes all once resolved, and the final prices hold once cancelled or
+    /// expired.
+    fn settlement_prices(&self) -> Option<(i128, i128)> {
+        match (&self.status, &self.winner) {
+            (PredictionStatus::Resolved, Resolution::Yes) => Some((ONE, 0)),
+            (PredictionStatus::Resolved, Resolution::No) => Some((0, ONE)),
+            (PredictionStatus::Cancelled | PredictionStatus::Expired, _) => {
+                Some((self.void_price, ONE - self.void_price))
+            }
+            _ => None,
+        }
+    }
+
+    /// What the given tokens redeem for, rounded down, or None while the
+    /// market is unsettled.
+    fn settled_value(&self, yes_tokens: i128, no_tokens: i128) -> Option<i128> {
+        let (yes_price, no_price) = self.settlement_prices()?;
+        Some((yes_tokens * yes_price + no_tokens * no_price) / ONE)
+    }
+
+    /// What `position` redeems for now.
+    pub(crate) fn payout_for(&self, position: &CpmmPosition) -> Result<i128, Error> {
+        if position.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let payout = self
+            .settled_value(position.yes_tokens, position.no_tokens)
+            .ok_or(Error::NotResolved)?;
+        if payout == 0 {
+            return Err(Error::LostPrediction);
+        }
+        Ok(payout)
+    }
+
+    /// End the market without a winner, at the current prices.
+    fn void(&mut self, status: PredictionStatus) {
+        self.void_price = self.prices().0;
+        self.status = status;
+    }
+}
+
+/// Whether the market still takes trades: it is open, before its unlock
+/// time, and trading is not paused.
+fn is_trading(env: &Env, prediction: &CpmmPrediction) -> bool {
+    prediction.status == PredictionStatus::Open
+        && env.ledger().timestamp() < prediction.unlock_time
+        && storage::get_paused_since(env, Operation::Stake).is_none()
+}
+
+/// Redeem a user's tokens in a resolved, cancelled or expired CPMM
+/// prediction.
+pub(crate) fn settle_cpmm_claim(
+    env: &Env,
+    prediction: &CpmmPrediction,
+    user: &Address,
+) -> Result<i128, Error> {
+    let mut position =
+        storage::get_cpmm_position(env, prediction.id, user).ok_or(Error::StakeNotFound)?;
+    let payout = prediction.payout_for(&position)?;
+
+    position.claimed = true;
+    storage::set_cpmm_position(env, prediction.id, &position);
+
+    let token_client = token::Client::new(env, &prediction.token);
+    token_client.transfer(&env.current_contract_address(), user, &payout);
+
+    SharesRedeemed {
+        prediction_id: prediction.id,
+        user: user.clone(),
+        payout,
+    }
+    .publish(env);
+
+    Ok(payout)
+}
+
+/// Outcome tokens held by a user.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct CpmmPosition {
+    pub user: Address,
+    pub yes_tokens: i128,
+    pub no_tokens: i128,
+    pub claimed: bool,
+}
+
+impl CpmmPosition {
+    /// The user's tokens in a market. A user's first tokens in the market
+    /// add it to their index, unless they already provide liquidity to it.
+    fn load(env: &Env, prediction_id: u64, user: &Address) -> Self {
+        storage::get_cpmm_position(env, prediction_id, user).unwrap_or_else(|| {
+            if storage::get_lp_position(env, prediction_id, user).is_none() {
+                storage::add_user_position(env, user, prediction_id, MarketKind::Cpmm);
+            }
+            CpmmPosition {
+                user: user.clone(),
+                yes_tokens: 0,
+                no_tokens: 0,
+                claimed: false,
+            }
+        })
+    }
+
+    pub(crate) fn tokens(&self, outcome: bool) -> i128 {
+        if outcome {
+            self.yes_tokens
+        } else {
+            self.no_tokens
+        }
+    }
+
+    fn add_tokens(&mut self, outcome: bool, tokens: i128) {
+        if outcome {
+            self.yes_tokens += tokens;
+        } else {
+            self.no_tokens += tokens;
+        }
+    }
+}
+
+/// A liquidity provider's pool shares. `fee_debt` is the part of the virtual
+/// fee total that was already there when the shares were added.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LpPosition {
+    pub provider: Address,
+    pub shares: i128,
+    pub fee_debt: i128,
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Create a CPMM prediction seeded with `liquidity` by the creator, who
+    /// pays the creation fee plus the liquidity and receives the first pool
+    /// shares. Each trade pays `fee_bps` to the liquidity providers.
+    pub fn create_cpmm(
+        env: Env,
+        creator: Address,
+        question: String,
+        unlock_time: u64,
+        liquidity: i128,
+        fee_bps: u32,
+        token: Address,
+    ) -> Result<u64, Error> {
+        creator.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Create)?;
+
+        // Validate inputs
+        let config = storage::get_config(&env);
+        validate_market(&env, &config, &question, unlock_time)?;
+
+        if liquidity < config.min_stake || liquidity > MAX_SETS {
+            return Err(Error::InvalidLiquidity);
+        }
+
+        if fee_bps > MAX_FEE_BPS {
+            return Err(Error::InvalidFee);
+        }
+
+        let count = next_prediction_id(&env);
+
+        // Transfer creation fee + liquidity from creator
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(
+            &creator,
+            env.current_contract_address(),
+            &(config.creation_fee + liquidity),
+        );
+        add_to_treasury(&env, &token, config.treasury_share());
+
+        let prediction = CpmmPrediction {
+            id: count,
+            creator,
+            question,
+            token,
+            unlock_time,
+            resolve_deadline: unlock_time + config.resolution_window,
+            pause_offset: paused_total(&env, Operation::Resolve),
+            fee_bps,
+            yes_reserve: liquidity,
+            no_reserve: liquidity,
+            sets: liquidity,
+            lp_supply: liquidity,
+            fees: 0,
+            status: PredictionStatus::Open,
+            winner: Resolution::Pending,
+            void_price: 0,
+        };
+        storage::set_cpmm(&env, &prediction);
+
+        let position = LpPosition {
+            provider: prediction.creator.clone(),
+            shares: liquidity,
+            fee_debt: 0,
+        };
+        storage::set_lp_position(&env, count, &position);
+        storage::add_user_position(&env, &position.provider, count, MarketKind::Cpmm);
+
+        CpmmCreated {
+            prediction_id: count,
+            creator: position.provider,
+            question: prediction.question,
+            token: prediction.token,
+            unlock_time,
+            resolve_deadline: prediction.resolve_deadline,
+            liquidity,
+            fee_bps,
+            creation_fee: config.creation_fee,
+        }
+        .publish(&env);
+
+        Ok(count)
+    }
+
+    /// Add `amount` of liquidity to an open CPMM prediction. Returns the
+    /// pool shares received. Outcome tokens the pool cannot take at its
+    /// current ratio are credited to the provider.
+    pub fn add_liquidity(
+        env: Env,
+        prediction_id: u64,
+        provider: Address,
+        amount: i128,
+        token: Address,
+    ) -> Result<i128, Error> {
+        provider.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        if amount < storage::get_config(&env).min_stake {
+            return Err(Error::InvalidLiquidity);
+        }
+
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_trading(
+            &env,
+            &prediction.status,
+            prediction.unlock_time,
+            &prediction.token,
+            &token,
+        )?;
+
+        if prediction.sets + amount > MAX_SETS {
+            return Err(Error::InvalidLiquidity);
+        }
+
+        // Add to both reserves in proportion, measured against the larger one
+        let pool_weight = prediction.yes_reserve.max(prediction.no_reserve);
+        let yes_added = amount * prediction.yes_reserve / pool_weight;
+        let no_added = amount * prediction.no_reserve / pool_weight;
+        let shares = amount * prediction.lp_supply / pool_weight;
+
+        // Buy into the fees already in the pool, rounded up
+        let fee_debt = (prediction.fees * shares + prediction.lp_supply - 1) / prediction.lp_supply;
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&provider, env.current_contract_address(), &amount);
+
+        prediction.yes_reserve += yes_added;
+        prediction.no_reserve += no_added;
+        prediction.sets += amount;
+        prediction.lp_supply += shares;
+        prediction.fees += fee_debt;
+        storage::set_cpmm(&env, &prediction);
+
+        // A provider's first liquidity adds the market to their index, unless
+        // they already hold its tokens
+        let mut position = match storage::get_lp_position(&env, prediction_id, &provider) {
+            Some(position) => position,
+            None => {
+                if storage::get_cpmm_position(&env, prediction_id, &provider).is_none() {
+                    storage::add_user_position(&env, &provider, prediction_id, MarketKind::Cpmm);
+                }
+                LpPosition {
+                    provider: provider.clone(),
+                    shares: 0,
+                    fee_debt: 0,
+                }
+            }
+        };
+        position.shares += shares;
+        position.fee_debt += fee_debt;
+        storage::set_lp_position(&env, prediction_id, &position);
+
+        let yes_returned = amount - yes_added;
+        let no_returned = amount - no_added;
+        if yes_returned > 0 || no_returned > 0 {
+            let mut tokens = CpmmPosition::load(&env, prediction_id, &provider);
+            tokens.add_tokens(true, yes_returned);
+            tokens.add_tokens(false, no_returned);
+            storage::set_cpmm_position(&env, prediction_id, &tokens);
+        }
+
+        LiquidityAdded {
+            prediction_id,
+            provider,
+            amount,
+            lp_shares: shares,
+            yes_returned,
+            no_returned,
+        }
+        .publish(&env);
+
+        Ok(shares)
+    }
+
+    /// Buy outcome tokens (`true` for YES) with `amount` of collateral. Fails
+    /// with `SlippageExceeded` if fewer than `min_tokens` would be bought.
+    /// Returns the tokens bought.
+    pub fn buy_outcome(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        outcome: bool,
+        amount: i128,
+        min_tokens: i128,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        if amount <= 0 {
+            return Err(Error::StakeTooLow);
+        }
+
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_trading(
+            &env,
+            &prediction.status,
+            prediction.unlock_time,
+            &prediction.token,
+            &token,
+        )?;
+
+        let (tokens, fee) = prediction.buy(outcome, amount)?;
+        if tokens < min_tokens {
+            return Err(Error::SlippageExceeded);
+        }
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&user, env.current_contract_address(), &amount);
+
+        storage::set_cpmm(&env, &prediction);
+
+        let mut position = CpmmPosition::load(&env, prediction_id, &user);
+        position.add_tokens(outcome, tokens);
+        storage::set_cpmm_position(&env, prediction_id, &position);
+
+        SharesBought {
+            prediction_id,
+            user,
+            outcome,
+            shares: tokens,
+            cost: amount - fee,
+            fee,
+            yes_price: prediction.prices().0,
+        }
+        .publish(&env);
+
+        Ok(tokens)
+    }
+
+    /// Sell `tokens` of an outcome back to the pool. Fails with
+    /// `SlippageExceeded` if the proceeds are below `min_proceeds`. Returns
+    /// what was received.
+    pub fn sell_outcome(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        outcome: bool,
+        tokens: i128,
+        min_proceeds: i128,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        if tokens <= 0 {
+            return Err(Error::InvalidShares);
+        }
+
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_trading(
+            &env,
+            &prediction.status,
+            prediction.unlock_time,
+            &prediction.token,
+            &token,
+        )?;
+
+        let mut position =
+            storage::get_cpmm_position(&env, prediction_id, &user).ok_or(Error::StakeNotFound)?;
+        if position.tokens(outcome) < tokens {
+            return Err(Error::InsufficientShares);
+        }
+
+        let proceeds = prediction.sell(outcome, tokens);
+        if proceeds < min_proceeds {
+            return Err(Error::SlippageExceeded);
+        }
+        storage::set_cpmm(&env, &prediction);
+
+        position.add_tokens(outcome, -tokens);
+        storage::set_cpmm_position(&env, prediction_id, &position);
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&env.current_contract_address(), &user, &proceeds);
+
+        SharesSold {
+            prediction_id,
+            user,
+            outcome,
+            shares: tokens,
+            proceeds,
+            yes_price: prediction.prices().0,
+        }
+        .publish(&env);
+
+        Ok(proceeds)
+    }
+
+    /// Quote how many tokens `buy_outcome` would give for `amount` right now
+    pub fn quote_buy_outcome(
+        env: Env,
+        prediction_id: u64,
+        outcome: bool,
+        amount: i128,
+    ) -> Result<i128, Error> {
+        if amount <= 0 {
+            return Err(Error::StakeTooLow);
+        }
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        Ok(prediction.buy(outcome, amount)?.0)
+    }
+
+    /// Quote what `sell_outcome` would pay for `tokens` right now
+    pub fn quote_sell_outcome(
+        env: Env,
+        prediction_id: u64,
+        outcome: bool,
+        tokens: i128,
+    ) -> Result<i128, Error> {
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        // No one can hold more tokens than there are sets
+        if tokens <= 0 || tokens > prediction.sets {
+            return Err(Error::InvalidShares);
+        }
+        Ok(prediction.sell(outcome, tokens))
+    }
+
+    /// Get the current (YES, NO) prices of a CPMM prediction, scaled by
+    /// 10^18 so they sum to 10^18
+    pub fn get_cpmm_prices(env: Env, prediction_id: u64) -> Result<(i128, i128), Error> {
+        let prediction = storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        Ok(prediction.prices())
+    }
+
+    /// Resolve a CPMM prediction (resolver only)
+    pub fn resolve_cpmm(
+        env: Env,
+        resolver: Address,
+        prediction_id: u64,
+        outcome: bool,
+    ) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
+
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        require_unsettled(&prediction.status)?;
+
+        let current_time = env.ledger().timestamp();
+        if current_time < prediction.unlock_time {
+            return Err(Error::ResolveTooEarly);
+        }
+        if current_time
+            >= extended_deadline(&env, prediction.resolve_deadline, prediction.pause_offset)
+        {
+            return Err(Error::ResolutionExpired);
+        }
+
+        prediction.status = PredictionStatus::Resolved;
+        prediction.winner = if outcome {
+            Resolution::Yes
+        } else {
+            Resolution::No
+        };
+        storage::set_cpmm(&env, &prediction);
+
+        CpmmResolved {
+            prediction_id,
+            resolver,
+            outcome,
+            yes_reserve: prediction.yes_reserve,
+            no_reserve: prediction.no_reserve,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Cancel an unresolved CPMM prediction (resolver; the creator while
+    /// they are the only provider and nobody holds tokens; or anyone once
+    /// resolution has been paused for `EMERGENCY_DELAY` and the market can
+    /// no longer be traded). Tokens then redeem and liquidity withdraws at
+    /// the final prices.
+    pub fn cancel_cpmm(env: Env, caller: Address, prediction_id: u64) -> Result<(), Error> {
+        caller.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        if !has_role(&env, Role::Resolver, &caller)? {
+            // Only the creator's own liquidity may be in the market
+            let shares = storage::get_lp_position(&env, prediction_id, &prediction.creator)
+                .map_or(0, |position| position.shares);
+            let sole_provider = caller == prediction.creator
+                && prediction.lp_supply == shares
+                && prediction.yes_reserve == prediction.sets
+                && prediction.no_reserve == prediction.sets;
+
+            if !sole_provider {
+                if !emergency_open(&env) {
+                    return Err(if caller == prediction.creator {
+                        Error::CannotCancel
+                    } else {
+                        Error::Unauthorized
+                    });
+                }
+                // The market settles at its current price, so it must not be
+                // possible to trade it to a new price and cancel at once
+                if is_trading(&env, &prediction) {
+                    return Err(Error::EmergencyNotAvailable);
+                }
+            }
+        }
+
+        prediction.void(PredictionStatus::Cancelled);
+        storage::set_cpmm(&env, &prediction);
+
+        CpmmCancelled {
+            prediction_id,
+            caller,
+            yes_reserve: prediction.yes_reserve,
+            no_reserve: prediction.no_reserve,
+            yes_price: prediction.void_price,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Mark a CPMM prediction that was not resolved by its deadline as
+    /// expired. Tokens then redeem and liquidity withdraws at the final
+    /// prices. Anyone can call this.
+    pub fn expire_cpmm(env: Env, prediction_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        let deadline =
+            extended_deadline(&env, prediction.resolve_deadline, prediction.pause_offset);
+        if env.ledger().timestamp() < deadline {
+            return Err(Error::DeadlineNotReached);
+        }
+
+        prediction.void(PredictionStatus::Expired);
+        storage::set_cpmm(&env, &prediction);
+
+        CpmmExpired {
+            prediction_id,
+            yes_reserve: prediction.yes_reserve,
+            no_reserve: prediction.no_reserve,
+            yes_price: prediction.void_price,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Redeem outcome tokens from a CPMM prediction: one base unit of the
+    /// token per winning token once resolved, or every token at the final
+    /// prices once cancelled or expired
+    pub fn claim_cpmm(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let prediction = storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        settle_cpmm_claim(&env, &prediction, &user)
+    }
+
+    /// Withdraw all of a provider's liquidity from a resolved, cancelled or
+    /// expired CPMM prediction: their share of the reserves at the
+    /// settlement prices plus their fees
+    pub fn withdraw_liquidity(
+        env: Env,
+        prediction_id: u64,
+        provider: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        provider.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let mut prediction =
+            storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        let mut position =
+            storage::get_lp_position(&env, prediction_id, &provider).ok_or(Error::StakeNotFound)?;
+        if position.shares == 0 {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let shares = position.shares;
+        let supply = prediction.lp_supply;
+        let yes_removed = prediction.yes_reserve * shares / supply;
+        let no_removed = prediction.no_reserve * shares / supply;
+        let fee_slice = prediction.fees * shares / supply;
+        let fees = (fee_slice - position.fee_debt).max(0);
+        let amount = prediction
+            .settled_value(yes_removed, no_removed)
+            .ok_or(Error::NotResolved)?;
+
+        prediction.yes_reserve -= yes_removed;
+        prediction.no_reserve -= no_removed;
+        prediction.lp_supply -= shares;
+        prediction.fees -= fee_slice;
+        storage::set_cpmm(&env, &prediction);
+
+        position.shares = 0;
+        position.fee_debt = 0;
+        storage::set_lp_position(&env, prediction_id, &position);
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&env.current_contract_address(), &provider, &(amount + fees));
+
+        LiquidityWithdrawn {
+            prediction_id,
+            provider,
+            lp_shares: shares,
+            amount,
+            fees,
+        }
+        .publish(&env);
+
+        Ok(amount + fees)
+    }
+
+    /// Get CPMM prediction details
+    pub fn get_cpmm(env: Env, prediction_id: u64) -> Result<CpmmPrediction, Error> {
+        storage::get_cpmm(&env, prediction_id).ok_or(Error::PredictionNotFound)
+    }
+
+    /// Get a user's outcome tokens in a CPMM prediction
+    pub fn get_cpmm_position(env: Env, prediction_id: u64, user: Address) -> Option<CpmmPosition> {
+        storage::get_cpmm_position(&env, prediction_id, &user)
+    }
+
+    /// Get a provider's pool shares in a CPMM prediction
+    pub fn get_lp_position(env: Env, prediction_id: u64, provider: Address) -> Option<LpPosition> {
+        storage::get_lp_position(&env, prediction_id, &provider)
+    }
+}
@@ -0,0 +1,649 @@
+//! Contract events.
+//!
+//! Every state transition publishes one event so that an indexer can rebuild
+//! the full history of a market without reading contract storage. All events
+//! share the same layout:
+//!
+//! - topics: `["timelock_v2", <event name>]` followed by the `#[topic]`
+//!   fields, which for market events are the prediction id and the address
+//!   that acted
+//! - data: a map keyed by the remaining field names
+//!
+//! The `_v2` suffix is the schema version. It is bumped whenever a field is
+//! removed or changes meaning, so consumers can filter on the versions they
+//! understand. Adding fields does not bump the version.
+//!
+//! - v1: the original schema.
+//! - v2: `created` replaced `unlock_time` with `close_time` and
+//!   `resolve_after` when closing and resolving a binary prediction were
+//!   split. The `admin` field of the resolution events became `resolver`,
+//!   that of `treasury_withdrawn` `treasurer` and that of `config_updated`
+//!   `config_manager`, since each now holds the account of that role.
+
+use soroban_sdk::{contractevent, Address, BytesN, String, Vec};
+
+use crate::{Config, Operation, Role};
+
+/// A prediction was created. `creator` is also the first staker.
+#[contractevent(topics = ["timelock_v2", "created"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PredictionCreated {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub close_time: u64,
+    pub resolve_after: u64,
+    pub resolve_deadline: u64,
+    pub choice: bool,
+    pub amount: i128,
+    pub creation_fee: i128,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+}
+
+/// A user staked on a prediction. `amount` is this stake and `total` the
+/// user's whole stake after it; pools are the totals after the stake.
+#[contractevent(topics = ["timelock_v2", "staked"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Staked {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub choice: bool,
+    pub amount: i128,
+    pub total: i128,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+}
+
+/// Staking on a prediction closed. Pools are the totals at closing.
+#[contractevent(topics = ["timelock_v2", "locked"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PredictionLocked {
+    #[topic]
+    pub prediction_id: u64,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+}
+
+/// A prediction was resolved by `resolver`. `platform_fee` is the fee credited
+/// to the treasury for the whole market.
+#[contractevent(topics = ["timelock_v2", "resolved"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Resolved {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub winner: bool,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+    pub platform_fee: i128,
+}
+
+/// A prediction was resolved as invalid by `resolver`; stakes are returned.
+#[contractevent(topics = ["timelock_v2", "resolved_invalid"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResolvedInvalid {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+}
+
+/// A prediction was cancelled by a resolver or its creator.
+#[contractevent(topics = ["timelock_v2", "cancelled"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cancelled {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub caller: Address,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+}
+
+/// A prediction passed its resolution deadline without being resolved;
+/// stakes are refunded.
+#[contractevent(topics = ["timelock_v2", "expired"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Expired {
+    #[topic]
+    pub prediction_id: u64,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+}
+
+/// A staker took back their stake from a cancelled or expired prediction of
+/// any type.
+#[contractevent(topics = ["timelock_v2", "refunded"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Refunded {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub amount: i128,
+}
+
+/// Part or all of a stake moved from `from` to `to`. `remaining` is what
+/// `from` still holds and `total` what `to` now holds.
+#[contractevent(topics = ["timelock_v2", "position_transferred"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PositionTransferred {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub from: Address,
+    #[topic]
+    pub to: Address,
+    pub choice: bool,
+    pub amount: i128,
+    pub remaining: i128,
+    pub total: i128,
+}
+
+/// A staker left an open prediction early. `amount` is what they got back
+/// and `penalty` what they left behind, split between the treasury and the
+/// pool; pools are the totals after the withdrawal.
+#[contractevent(topics = ["timelock_v2", "stake_withdrawn"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StakeWithdrawn {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub amount: i128,
+    pub penalty: i128,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+}
+
+/// A staker took their stake back out of an unresolved prediction after
+/// resolution was paused for too long, or an LMSR trader sold their shares
+/// back. Applies to every market type except CPMM, which anyone can cancel
+/// instead.
+#[contractevent(topics = ["timelock_v2", "emergency_withdrawn"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EmergencyWithdrawn {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub amount: i128,
+}
+
+/// A categorical prediction was created. `creator` is also the first staker.
+#[contractevent(topics = ["timelock_v2", "cat_created"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CategoricalCreated {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64,
+    pub outcomes: Vec<String>,
+    pub outcome: u32,
+    pub amount: i128,
+    pub creation_fee: i128,
+}
+
+/// A user staked on an outcome. Pools are the totals after the stake.
+#[contractevent(topics = ["timelock_v2", "cat_staked"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutcomeStaked {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub outcome: u32,
+    pub amount: i128,
+    pub pools: Vec<i128>,
+}
+
+/// A categorical prediction was resolved by `resolver`.
+/// `platform_fee` is the fee credited to the treasury for the whole market.
+#[contractevent(topics = ["timelock_v2", "cat_resolved"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutcomeResolved {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub outcome: u32,
+    pub pools: Vec<i128>,
+    pub platform_fee: i128,
+}
+
+/// A categorical prediction was resolved as invalid by `resolver`;
+/// stakes are returned.
+#[contractevent(topics = ["timelock_v2", "cat_resolved_invalid"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutcomeResolvedInvalid {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub pools: Vec<i128>,
+}
+
+/// A categorical prediction was cancelled by a resolver or its creator.
+#[contractevent(topics = ["timelock_v2", "cat_cancelled"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutcomeCancelled {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub caller: Address,
+    pub pools: Vec<i128>,
+}
+
+/// A categorical prediction passed its resolution deadline without being
+/// resolved; stakes are refunded.
+#[contractevent(topics = ["timelock_v2", "cat_expired"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutcomeExpired {
+    #[topic]
+    pub prediction_id: u64,
+    pub pools: Vec<i128>,
+}
+
+/// A scalar prediction was created. `creator` is also the first staker.
+#[contractevent(topics = ["timelock_v2", "scalar_created"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScalarCreated {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64,
+    pub lower_bound: i128,
+    pub upper_bound: i128,
+    pub long: bool,
+    pub amount: i128,
+    pub creation_fee: i128,
+}
+
+/// A user went LONG or SHORT on a scalar prediction. Pools are the totals
+/// after the stake.
+#[contractevent(topics = ["timelock_v2", "scalar_staked"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScalarStaked {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub long: bool,
+    pub amount: i128,
+    pub long_pool: i128,
+    pub short_pool: i128,
+}
+
+/// A scalar prediction was resolved by `resolver`. `value` is what
+/// it reported and `outcome` the value clamped to the bounds. `platform_fee`
+/// is the fee credited to the treasury for the whole market.
+#[contractevent(topics = ["timelock_v2", "scalar_resolved"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScalarResolved {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub value: i128,
+    pub outcome: i128,
+    pub long_pool: i128,
+    pub short_pool: i128,
+    pub platform_fee: i128,
+}
+
+/// A scalar prediction was resolved as invalid by `resolver`;
+/// stakes are returned.
+#[contractevent(topics = ["timelock_v2", "scalar_resolved_invalid"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScalarResolvedInvalid {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub long_pool: i128,
+    pub short_pool: i128,
+}
+
+/// A scalar prediction was cancelled by a resolver or its creator.
+#[contractevent(topics = ["timelock_v2", "scalar_cancelled"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScalarCancelled {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub caller: Address,
+    pub long_pool: i128,
+    pub short_pool: i128,
+}
+
+/// A scalar prediction passed its resolution deadline without being
+/// resolved; stakes are refunded.
+#[contractevent(topics = ["timelock_v2", "scalar_expired"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScalarExpired {
+    #[topic]
+    pub prediction_id: u64,
+    pub long_pool: i128,
+    pub short_pool: i128,
+}
+
+/// An LMSR prediction was created. `subsidy` is what the creator paid in to
+/// cover the market maker's worst-case loss.
+#[contractevent(topics = ["timelock_v2", "lmsr_created"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LmsrCreated {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64,
+    pub liquidity: i128,
+    pub subsidy: i128,
+    pub creation_fee: i128,
+}
+
+/// A user bought LMSR or CPMM shares. `cost` went to the market and `fee`
+/// to the treasury (LMSR) or the liquidity providers (CPMM); `yes_price` is
+/// the YES price after the trade.
+#[contractevent(topics = ["timelock_v2", "shares_bought"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SharesBought {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub outcome: bool,
+    pub shares: i128,
+    pub cost: i128,
+    pub fee: i128,
+    pub yes_price: i128,
+}
+
+/// A user sold LMSR or CPMM shares back to the market. `yes_price` is the
+/// YES price after the trade.
+#[contractevent(topics = ["timelock_v2", "shares_sold"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SharesSold {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub outcome: bool,
+    pub shares: i128,
+    pub proceeds: i128,
+    pub yes_price: i128,
+}
+
+/// An LMSR prediction was resolved by `resolver`. `creator_return`
+/// is what was left for the creator after reserving every winning share.
+#[contractevent(topics = ["timelock_v2", "lmsr_resolved"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LmsrResolved {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub outcome: bool,
+    pub yes_shares: i128,
+    pub no_shares: i128,
+    pub creator_return: i128,
+}
+
+/// An LMSR prediction was cancelled by `caller`, a resolver or its creator.
+/// Shares redeem at the final prices and `creator_return` went back to the
+/// creator.
+#[contractevent(topics = ["timelock_v2", "lmsr_cancelled"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LmsrCancelled {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub caller: Address,
+    pub yes_shares: i128,
+    pub no_shares: i128,
+    pub creator_return: i128,
+}
+
+/// An LMSR prediction passed its resolution deadline unresolved. Shares
+/// redeem at the final prices and `creator_return` went back to the creator.
+#[contractevent(topics = ["timelock_v2", "lmsr_expired"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LmsrExpired {
+    #[topic]
+    pub prediction_id: u64,
+    pub yes_shares: i128,
+    pub no_shares: i128,
+    pub creator_return: i128,
+}
+
+/// A user redeemed their winning LMSR or CPMM shares, one base unit each,
+/// or their shares at the final prices of a cancelled or expired market.
+#[contractevent(topics = ["timelock_v2", "shares_redeemed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SharesRedeemed {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub payout: i128,
+}
+
+/// A CPMM prediction was created, seeded with `liquidity` by its creator.
+#[contractevent(topics = ["timelock_v2", "cpmm_created"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CpmmCreated {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64,
+    pub liquidity: i128,
+    pub fee_bps: u32,
+    pub creation_fee: i128,
+}
+
+/// Liquidity was added to a CPMM prediction. Outcome tokens the pool could
+/// not take at its current ratio went to the provider as `yes_returned` or
+/// `no_returned`.
+#[contractevent(topics = ["timelock_v2", "liquidity_added"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidityAdded {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub provider: Address,
+    pub amount: i128,
+    pub lp_shares: i128,
+    pub yes_returned: i128,
+    pub no_returned: i128,
+}
+
+/// A CPMM prediction was resolved by `resolver`.
+#[contractevent(topics = ["timelock_v2", "cpmm_resolved"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CpmmResolved {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub resolver: Address,
+    pub outcome: bool,
+    pub yes_reserve: i128,
+    pub no_reserve: i128,
+}
+
+/// A CPMM prediction was cancelled by `caller`: a resolver, its creator, or
+/// anyone once the emergency exit is open and the market can no longer be
+/// traded. Tokens redeem at `yes_price` and its complement.
+#[contractevent(topics = ["timelock_v2", "cpmm_cancelled"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CpmmCancelled {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub caller: Address,
+    pub yes_reserve: i128,
+    pub no_reserve: i128,
+    pub yes_price: i128,
+}
+
+/// A CPMM prediction passed its resolution deadline unresolved. Tokens
+/// redeem at `yes_price` and its complement.
+#[contractevent(topics = ["timelock_v2", "cpmm_expired"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CpmmExpired {
+    #[topic]
+    pub prediction_id: u64,
+    pub yes_reserve: i128,
+    pub no_reserve: i128,
+    pub yes_price: i128,
+}
+
+/// A liquidity provider withdrew from a settled CPMM prediction. `amount`
+/// is their share of the reserves at the settlement prices, `fees` their
+/// share of the fees.
+#[contractevent(topics = ["timelock_v2", "liquidity_withdrawn"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LiquidityWithdrawn {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub provider: Address,
+    pub lp_shares: i128,
+    pub amount: i128,
+    pub fees: i128,
+}
+
+/// A winner claimed their payout from any market type. `platform_fee` is the
+/// fee taken from the whole market at resolution, not from this claim.
+#[contractevent(topics = ["timelock_v2", "claimed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Claimed {
+    #[topic]
+    pub prediction_id: u64,
+    #[topic]
+    pub user: Address,
+    pub stake: i128,
+    pub payout: i128,
+    pub platform_fee: i128,
+}
+
+/// `admin` proposed `new_admin` as its successor.
+#[contractevent(topics = ["timelock_v2", "admin_proposed"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AdminProposed {
+    #[topic]
+    pub admin: Address,
+    pub new_admin: Address,
+}
+
+/// `new_admin` accepted the proposal and replaced `previous_admin`.
+#[contractevent(topics = ["timelock_v2", "admin_transferred"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AdminTransferred {
+    #[topic]
+    pub previous_admin: Address,
+    pub new_admin: Address,
+}
+
+/// `admin` granted `role` to `account`.
+#[contractevent(topics = ["timelock_v2", "role_granted"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RoleGranted {
+    #[topic]
+    pub role: Role,
+    #[topic]
+    pub account: Address,
+    pub admin: Address,
+}
+
+/// `admin` revoked `role` from `account`.
+#[contractevent(topics = ["timelock_v2", "role_revoked"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RoleRevoked {
+    #[topic]
+    pub role: Role,
+    #[topic]
+    pub account: Address,
+    pub admin: Address,
+}
+
+/// `pauser` halted `operation`.
+#[contractevent(topics = ["timelock_v2", "paused"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Paused {
+    #[topic]
+    pub operation: Operation,
+    pub pauser: Address,
+}
+
+/// `pauser` resumed `operation`.
+#[contractevent(topics = ["timelock_v2", "unpaused"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Unpaused {
+    #[topic]
+    pub operation: Operation,
+    pub pauser: Address,
+}
+
+/// The admin replaced the contract code with the wasm `wasm_hash`.
+#[contractevent(topics = ["timelock_v2", "upgraded"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ContractUpgraded {
+    #[topic]
+    pub admin: Address,
+    pub wasm_hash: BytesN<32>,
+}
+
+/// The admin migrated contract-wide storage between schema versions.
+#[contractevent(topics = ["timelock_v2", "migrated"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Migrated {
+    #[topic]
+    pub admin: Address,
+    pub from_version: u32,
+    pub to_version: u32,
+}
+
+/// `config_manager` replaced the protocol parameters.
+#[contractevent(topics = ["timelock_v2", "config_updated"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConfigUpdated {
+    #[topic]
+    pub config_manager: Address,
+    pub config: Config,
+}
+
+/// `treasurer` withdrew funds from the treasury of `token`.
+#[contractevent(topics = ["timelock_v2", "treasury_withdrawn"])]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TreasuryWithdrawn {
+    #[topic]
+    pub token: Address,
+    #[topic]
+    pub treasurer: Address,
+    pub amount: i128,
+    pub remaining: i128,
+}
@@ -0,0 +1,66 @@
+//! Deterministic fixed-point math.
+//!
+//! Values are `i128` scaled by `ONE` (18 decimals). Everything is integer
+//! arithmetic with truncating division, so every validator computes the same
+//! result bit for bit. Series are summed until the next term rounds to zero,
+//! which bounds the error to a few dozen units in the last place.
+
+pub const ONE: i128 = 1_000_000_000_000_000_000;
+pub const LN_2: i128 = 693_147_180_559_945_309;
+
+// e^(-42) is below the smallest representable value
+const EXP_CUTOFF: i128 = 42 * ONE;
+
+/// e^(-x) for x >= 0.
+pub fn exp_neg(x: i128) -> i128 {
+    if x >= EXP_CUTOFF {
+        return 0;
+    }
+
+    // e^(-x) = 2^(-k) * e^(-r) with 0 <= r < ln 2, so the series converges fast
+    let k = x / LN_2;
+    let r = x - k * LN_2;
+
+    let mut term = ONE;
+    let mut sum = ONE;
+    let mut n = 1;
+    while term != 0 {
+        term = -term * r / ONE / n;
+        sum += term;
+        n += 1;
+    }
+    sum >> k as u32
+}
+
+/// ln(1 + y) for 0 <= y <= ONE.
+pub fn ln_1p(y: i128) -> i128 {
+    // ln(z) = 2 * atanh(t) with t = (z - 1) / (z + 1), and t <= 1/3 here
+    let t = y * ONE / (2 * ONE + y);
+    let t_squared = t * t / ONE;
+
+    let mut power = t;
+    let mut sum = 0;
+    let mut n = 1;
+    while power != 0 {
+        sum += power / n;
+        power = power * t_squared / ONE;
+        n += 2;
+    }
+    2 * sum
+}
+
+/// The integer square root of `n >= 0`, rounded down.
+pub fn sqrt(n: i128) -> i128 {
+    if n < 2 {
+        return n;
+    }
+
+    // Newton's method from above converges to the floor
+    let mut x = n;
+    let mut y = (x + 1) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
@@ -1,46 +1,252 @@
 #![no_std]
 use soroban_sdk::{
-    contract, contractimpl, contracttype, token, Address, Env, String,
+    contract, contracterror, contractimpl, contracttype, token, Address, Env, String, Vec,
 };
 
-// Constants
-const CREATION_FEE: i128 = 50_0000000; // 50 XLM (7 decimals)
-const MIN_STAKE: i128 = 100_0000000; // 100 XLM
-const ONE_HOUR: u64 = 3600; // 1 hour in seconds
+mod access;
+mod batch;
+mod categorical;
+mod config;
+mod cpmm;
+mod events;
+mod fixed;
+mod listing;
+mod lmsr;
+mod pause;
+mod positions;
+mod quotes;
+mod scalar;
+mod storage;
+mod transfer;
+mod upgrade;
+mod withdrawal;
+pub use access::*;
+pub use batch::*;
+pub use categorical::*;
+pub use config::*;
+pub use cpmm::*;
+pub use events::*;
+pub use listing::*;
+pub use lmsr::*;
+pub use pause::*;
+pub use positions::*;
+pub use quotes::*;
+pub use scalar::*;
+pub use transfer::*;
+pub use upgrade::*;
+pub use withdrawal::*;
+
+// Errors
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    AlreadyInitialized = 1,
+    NotInitialized = 2,
+    Unauthorized = 3,
+    InvalidUnlockTime = 4,
+    InvalidQuestion = 5,
+    PredictionNotFound = 6,
+    AlreadyStaked = 7,
+    StakeTooLow = 8,
+    PredictionNotOpen = 9,
+    PredictionExpired = 10,
+    ResolveTooEarly = 11,
+    AlreadyResolved = 12,
+    NotResolved = 13,
+    StakeNotFound = 14,
+    AlreadyClaimed = 15,
+    LostPrediction = 16,
+    InsufficientTreasury = 17,
+    TokenMismatch = 18,
+    InvalidOutcomes = 19,
+    InvalidOutcome = 20,
+    InvalidBounds = 21,
+    CannotCancel = 22,
+    PredictionCancelled = 23,
+    NotCancelled = 24,
+    InvalidConfig = 25,
+    NoPendingAdmin = 26,
+    MigrationRequired = 27,
+    OperationPaused = 28,
+    EmergencyNotAvailable = 29,
+    BatchTooLarge = 30,
+    InvalidResolveTime = 31,
+    LockTooEarly = 32,
+    PredictionNotLocked = 33,
+    ResolutionExpired = 34,
+    DeadlineNotReached = 35,
+    CannotSwitchSides = 36,
+    TooManyIncrements = 37,
+    InvalidLiquidity = 38,
+    InvalidShares = 39,
+    SlippageExceeded = 40,
+    InsufficientShares = 41,
+    InvalidFee = 42,
+    InvalidTransfer = 43,
+    InvalidAmount = 44,
+}
 
 // Data Types
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub enum DataKey {
     Admin,
+    PendingAdmin,
+    Role(Role, Address),
+    Config,
+    Paused(Operation),
+    PausedTotal(Operation), // Time paused over pauses that have ended
+    SchemaVersion,
+    LegacyToken, // Token of version 1 markets, recorded by `migrate`
     PredictionCount,
     Prediction(u64),
     UserStake(u64, Address),
-    Treasury,
+    StakeHistory(u64, Address),
+    Treasury(Address),
+    Categorical(u64),
+    OutcomeStake(u64, Address),
+    Scalar(u64),
+    ScalarStake(u64, Address),
+    Lmsr(u64),
+    LmsrPosition(u64, Address),
+    Cpmm(u64),
+    CpmmPosition(u64, Address),
+    LpPosition(u64, Address),
+    UserPositionCount(Address),
+    UserPosition(Address, u32), // (prediction id, market kind) by index
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub enum PredictionStatus {
     Open,
-    Locked,
+    Locked, // Staking has closed, waiting to be resolved
     Resolved,
+    Cancelled,
+    Expired, // Not resolved by its deadline, stakes are refunded
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+#[repr(u32)]
+pub enum Resolution {
+    Pending = 0,
+    Yes = 1,
+    No = 2,
+    Invalid = 3, // Unanswerable question, every staker gets their stake back
+}
+
+/// Payout accounting for a market, fixed when it is resolved.
+///
+/// The platform fee is credited to the treasury once at resolution. Winners
+/// then share `prize_pool` pro-rata by stake; rounding leaves a little dust,
+/// which is credited to the treasury when the last eligible stake claims, so
+/// `platform_fee + paid_out + dust` always adds up to the total pool.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[contracttype]
+pub struct Settlement {
+    pub platform_fee: i128,
+    pub prize_pool: i128,
+    pub eligible_stake: i128, // Sum of the stakes sharing the prize pool
+    pub claimed_stake: i128,
+    pub paid_out: i128,
+    pub dust: i128,
+}
+
+impl Settlement {
+    /// Settle a market where `eligible_stake` shares the pool, taking the fee.
+    fn winners(env: &Env, token: &Address, total_pool: i128, eligible_stake: i128) -> Self {
+        let settlement = Self::projected(env, total_pool, eligible_stake);
+        add_to_treasury(env, token, settlement.platform_fee);
+        settlement
+    }
+
+    /// The settlement `winners` would produce, without taking the fee.
+    fn projected(env: &Env, total_pool: i128, eligible_stake: i128) -> Self {
+        let (prize_pool, platform_fee) = storage::get_config(env).split_pool(total_pool);
+
+        Settlement {
+            platform_fee,
+            prize_pool,
+            eligible_stake,
+            ..Default::default()
+        }
+    }
+
+    /// Settle a market where every stake is returned in full, with no fee.
+    fn refund(total_pool: i128) -> Self {
+        Settlement {
+            prize_pool: total_pool,
+            eligible_stake: total_pool,
+            ..Default::default()
+        }
+    }
+
+    /// User's share: (user_stake / eligible_stake) * prize_pool
+    fn payout(&self, amount: i128) -> i128 {
+        (amount * self.prize_pool) / self.eligible_stake
+    }
+
+    /// Record a claim, sweeping the rounding dust once everyone has claimed.
+    fn record_claim(&mut self, env: &Env, token: &Address, amount: i128, payout: i128) {
+        self.claimed_stake += amount;
+        self.paid_out += payout;
+
+        if self.claimed_stake == self.eligible_stake {
+            self.dust = self.prize_pool - self.paid_out;
+            add_to_treasury(env, token, self.dust);
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub struct Prediction {
     pub id: u64,
     pub creator: Address,
     pub question: String,
-    pub unlock_time: u64,
+    pub token: Address,
+    pub close_time: u64,       // Staking closes
+    pub resolve_after: u64,    // Earliest resolution
+    pub resolve_deadline: u64, // Expires if still unresolved by then, see `extended_deadline`
+    pub pause_offset: u64,     // Resolve pause time before creation
     pub yes_pool: i128,
     pub no_pool: i128,
+    pub forfeited: i128, // Exit penalties left in the pool for the winners
     pub status: PredictionStatus,
-    pub winner: Option<bool>, // None = not resolved, Some(true) = YES wins, Some(false) = NO wins
+    pub winner: Resolution,
+    pub settlement: Settlement,
 }
 
-#[derive(Clone)]
+impl Prediction {
+    /// What `claim` pays out for `stake`, or why it cannot be claimed.
+    fn payout_for(&self, stake: &Stake) -> Result<i128, Error> {
+        // Check if resolved
+        match self.status {
+            PredictionStatus::Cancelled => return Err(Error::PredictionCancelled),
+            PredictionStatus::Expired => return Err(Error::ResolutionExpired),
+            _ => {}
+        }
+        if self.winner == Resolution::Pending {
+            return Err(Error::NotResolved);
+        }
+
+        // Check if already claimed
+        if stake.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        // Invalid markets return each stake in full, otherwise check if user won
+        if self.winner != Resolution::Invalid && stake.choice != (self.winner == Resolution::Yes) {
+            return Err(Error::LostPrediction);
+        }
+
+        Ok(self.settlement.payout(stake.amount))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[contracttype]
 pub struct Stake {
     pub user: Address,
@@ -49,98 +255,413 @@ pub struct Stake {
     pub claimed: bool,
 }
 
+/// One change to a stake: the initial stake, a top-up, a transfer in, or a
+/// withdrawal or transfer out (negative).
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct StakeIncrement {
+    pub amount: i128,
+    pub timestamp: u64,
+}
+
+pub const MAX_STAKE_INCREMENTS: u32 = 100;
+
 #[contract]
 pub struct TimeLockContract;
 
+fn is_admin(env: &Env, address: &Address) -> Result<bool, Error> {
+    let stored_admin: Address = env
+        .storage()
+        .instance()
+        .get(&DataKey::Admin)
+        .ok_or(Error::NotInitialized)?;
+    Ok(*address == stored_admin)
+}
+
+fn require_admin(env: &Env, admin: &Address) -> Result<(), Error> {
+    if !is_admin(env, admin)? {
+        return Err(Error::Unauthorized);
+    }
+    Ok(())
+}
+
+fn add_to_treasury(env: &Env, token: &Address, amount: i128) {
+    let key = DataKey::Treasury(token.clone());
+    let current_treasury: i128 = env.storage().instance().get(&key).unwrap_or(0);
+    env.storage()
+        .instance()
+        .set(&key, &(current_treasury + amount));
+}
+
+/// Reserve the next prediction id. Ids are shared by every market type.
+fn next_prediction_id(env: &Env) -> u64 {
+    let count: u64 = env
+        .storage()
+        .instance()
+        .get(&DataKey::PredictionCount)
+        .unwrap_or(0)
+        + 1;
+    env.storage()
+        .instance()
+        .set(&DataKey::PredictionCount, &count);
+    count
+}
+
+/// Validate the fields every market type shares.
+fn validate_market(
+    env: &Env,
+    config: &Config,
+    question: &String,
+    unlock_time: u64,
+) -> Result<(), Error> {
+    let current_time = env.ledger().timestamp();
+    if unlock_time <= current_time + config.min_lead_time {
+        return Err(Error::InvalidUnlockTime);
+    }
+
+    if question.len() < 10 || question.len() > 200 {
+        return Err(Error::InvalidQuestion);
+    }
+
+    Ok(())
+}
+
+/// Check an LMSR or CPMM market is open for trading in `token`.
+fn require_trading(
+    env: &Env,
+    status: &PredictionStatus,
+    unlock_time: u64,
+    market_token: &Address,
+    token: &Address,
+) -> Result<(), Error> {
+    if *status != PredictionStatus::Open {
+        return Err(Error::PredictionNotOpen);
+    }
+
+    if env.ledger().timestamp() >= unlock_time {
+        return Err(Error::PredictionExpired);
+    }
+
+    if token != market_token {
+        return Err(Error::TokenMismatch);
+    }
+    Ok(())
+}
+
+/// Check a market of any type has not been resolved, cancelled or expired.
+fn require_unsettled(status: &PredictionStatus) -> Result<(), Error> {
+    match status {
+        PredictionStatus::Open | PredictionStatus::Locked => Ok(()),
+        PredictionStatus::Resolved => Err(Error::AlreadyResolved),
+        PredictionStatus::Cancelled => Err(Error::PredictionCancelled),
+        PredictionStatus::Expired => Err(Error::ResolutionExpired),
+    }
+}
+
+/// Whether a market of any type pays back stakes through `refund*`.
+fn is_refundable(status: &PredictionStatus) -> bool {
+    matches!(
+        status,
+        PredictionStatus::Cancelled | PredictionStatus::Expired
+    )
+}
+
+fn require_refundable(status: &PredictionStatus) -> Result<(), Error> {
+    if !is_refundable(status) {
+        return Err(Error::NotCancelled);
+    }
+    Ok(())
+}
+
+/// Send a stake back from a cancelled or expired market.
+fn pay_refund(env: &Env, prediction_id: u64, user: Address, token: &Address, amount: i128) {
+    let token_client = token::Client::new(env, token);
+    token_client.transfer(&env.current_contract_address(), &user, &amount);
+
+    Refunded {
+        prediction_id,
+        user,
+        amount,
+    }
+    .publish(env);
+}
+
+/// Load a binary prediction and check it can be resolved now.
+fn load_resolvable(env: &Env, prediction_id: u64) -> Result<Prediction, Error> {
+    let prediction =
+        storage::get_prediction(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+    // Check status
+    match prediction.status {
+        PredictionStatus::Locked => {}
+        PredictionStatus::Open => return Err(Error::PredictionNotLocked),
+        PredictionStatus::Cancelled => return Err(Error::PredictionCancelled),
+        PredictionStatus::Resolved => return Err(Error::AlreadyResolved),
+        PredictionStatus::Expired => return Err(Error::ResolutionExpired),
+    }
+
+    // Check time
+    let current_time = env.ledger().timestamp();
+    if current_time < prediction.resolve_after {
+        return Err(Error::ResolveTooEarly);
+    }
+    if current_time >= extended_deadline(env, prediction.resolve_deadline, prediction.pause_offset)
+    {
+        return Err(Error::ResolutionExpired);
+    }
+
+    Ok(prediction)
+}
+
+/// Load a binary prediction and check it accepts a stake of `amount`.
+fn load_stakeable(env: &Env, prediction_id: u64, amount: i128) -> Result<Prediction, Error> {
+    // Validate amount
+    if amount < storage::get_config(env).min_stake {
+        return Err(Error::StakeTooLow);
+    }
+
+    // Get prediction
+    let prediction =
+        storage::get_prediction(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+    // Check status
+    match prediction.status {
+        PredictionStatus::Open => {}
+        _ => return Err(Error::PredictionNotOpen),
+    }
+
+    // Check time
+    let current_time = env.ledger().timestamp();
+    if current_time >= prediction.close_time {
+        return Err(Error::PredictionExpired);
+    }
+
+    Ok(prediction)
+}
+
+/// Load a binary stake along with what `claim` would pay for it.
+fn load_claim(
+    env: &Env,
+    prediction_id: u64,
+    user: &Address,
+) -> Result<(Prediction, Stake, i128), Error> {
+    let prediction =
+        storage::get_prediction(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+    let stake = storage::get_stake(env, prediction_id, user).ok_or(Error::StakeNotFound)?;
+    let payout = prediction.payout_for(&stake)?;
+    Ok((prediction, stake, payout))
+}
+
+/// Record a claim returned by `load_claim` and pay it out. The prediction's
+/// dust is swept to the treasury once its last winner has claimed.
+fn settle_claim(
+    env: &Env,
+    user: &Address,
+    mut prediction: Prediction,
+    mut stake: Stake,
+    payout: i128,
+) {
+    prediction
+        .settlement
+        .record_claim(env, &prediction.token, stake.amount, payout);
+    storage::set_prediction(env, &prediction);
+
+    // Mark as claimed
+    stake.claimed = true;
+    storage::set_stake(env, prediction.id, &stake);
+
+    // Transfer winnings
+    let token_client = token::Client::new(env, &prediction.token);
+    token_client.transfer(&env.current_contract_address(), user, &payout);
+
+    Claimed {
+        prediction_id: prediction.id,
+        user: user.clone(),
+        stake: stake.amount,
+        payout,
+        platform_fee: prediction.settlement.platform_fee,
+    }
+    .publish(env);
+}
+
+/// Return a user's stake in full from a cancelled or expired binary
+/// prediction.
+fn refund_stake(env: &Env, prediction: &Prediction, user: &Address) -> Result<i128, Error> {
+    let mut stake = storage::get_stake(env, prediction.id, user).ok_or(Error::StakeNotFound)?;
+
+    if stake.claimed {
+        return Err(Error::AlreadyClaimed);
+    }
+
+    stake.claimed = true;
+    storage::set_stake(env, prediction.id, &stake);
+
+    pay_refund(
+        env,
+        prediction.id,
+        user.clone(),
+        &prediction.token,
+        stake.amount,
+    );
+
+    Ok(stake.amount)
+}
+
+/// Append a change to a binary stake's history.
+fn record_increment(
+    env: &Env,
+    prediction_id: u64,
+    user: &Address,
+    amount: i128,
+) -> Result<(), Error> {
+    let mut history = storage::get_stake_history(env, prediction_id, user);
+    if history.len() >= MAX_STAKE_INCREMENTS {
+        return Err(Error::TooManyIncrements);
+    }
+    history.push_back(StakeIncrement {
+        amount,
+        timestamp: env.ledger().timestamp(),
+    });
+    storage::set_stake_history(env, prediction_id, user, &history);
+    Ok(())
+}
+
+/// Collect the creation fee and the creator's initial stake.
+fn take_creation_payment(env: &Env, config: &Config, creator: &Address, token: &Address) {
+    // Transfer creation fee + initial stake from creator
+    let total_payment = config.creation_fee + config.min_stake;
+    let token_client = token::Client::new(env, token);
+    token_client.transfer(creator, env.current_contract_address(), &total_payment);
+
+    // Update treasury with its share of the creation fee, the rest stays in contract for burning
+    add_to_treasury(env, token, config.treasury_share());
+}
+
+/// Credit the exit penalties left in a prediction's pool to the treasury
+/// when it ends without winners to share them.
+fn release_forfeited(env: &Env, prediction: &Prediction) {
+    add_to_treasury(env, &prediction.token, prediction.forfeited);
+}
+
+/// Resolve a binary prediction as invalid so every stake is returned.
+fn resolve_as_invalid(env: &Env, resolver: Address, mut prediction: Prediction) {
+    prediction.status = PredictionStatus::Resolved;
+    prediction.winner = Resolution::Invalid;
+    prediction.settlement = Settlement::refund(prediction.yes_pool + prediction.no_pool);
+    release_forfeited(env, &prediction);
+
+    storage::set_prediction(env, &prediction);
+
+    ResolvedInvalid {
+        prediction_id: prediction.id,
+        resolver,
+        yes_pool: prediction.yes_pool,
+        no_pool: prediction.no_pool,
+    }
+    .publish(env);
+}
+
 #[contractimpl]
 impl TimeLockContract {
     /// Initialize the contract with an admin
-    pub fn initialize(env: Env, admin: Address) {
+    pub fn initialize(env: Env, admin: Address) -> Result<(), Error> {
         if env.storage().instance().has(&DataKey::Admin) {
-            panic!("Already initialized");
+            return Err(Error::AlreadyInitialized);
         }
-        
+
         env.storage().instance().set(&DataKey::Admin, &admin);
-        env.storage().instance().set(&DataKey::PredictionCount, &0u64);
-        env.storage().instance().set(&DataKey::Treasury, &0i128);
+        env.storage()
+            .instance()
+            .set(&DataKey::PredictionCount, &0u64);
+        storage::set_config(&env, &Config::default());
+        storage::set_schema_version(&env, SCHEMA_VERSION);
+        storage::extend_instance(&env);
+
+        Ok(())
     }
 
-    /// Create a new prediction
+    /// Create a new prediction. Staking closes at `close_time` and the
+    /// outcome can be resolved from `resolve_after`, which may be later. If
+    /// it is still unresolved `resolution_window` (see `Config`) after that,
+    /// anyone can `expire` it.
     pub fn create_prediction(
         env: Env,
         creator: Address,
         question: String,
-        unlock_time: u64,
+        close_time: u64,
+        resolve_after: u64,
         initial_choice: bool,
         token: Address,
-    ) -> u64 {
+    ) -> Result<u64, Error> {
         creator.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Create)?;
 
         // Validate inputs
-        let current_time = env.ledger().timestamp();
-        if unlock_time <= current_time + ONE_HOUR {
-            panic!("Unlock time must be at least 1 hour in the future");
+        let config = storage::get_config(&env);
+        validate_market(&env, &config, &question, close_time)?;
+        if resolve_after < close_time {
+            return Err(Error::InvalidResolveTime);
         }
 
-        if question.len() < 10 || question.len() > 200 {
-            panic!("Question must be 10-200 characters");
-        }
-
-        // Get next prediction ID
-        let mut count: u64 = env
-            .storage()
-            .instance()
-            .get(&DataKey::PredictionCount)
-            .unwrap_or(0);
-        count += 1;
-
-        // Transfer creation fee + initial stake from creator
-        let total_payment = CREATION_FEE + MIN_STAKE;
-        let token_client = token::Client::new(&env, &token);
-        token_client.transfer(&creator, &env.current_contract_address(), &total_payment);
-
-        // Update treasury with creation fee (70% to treasury, 30% stays in contract for burning)
-        let treasury_amount = (CREATION_FEE * 7) / 10;
-        let current_treasury: i128 = env.storage().instance().get(&DataKey::Treasury).unwrap_or(0);
-        env.storage()
-            .instance()
-            .set(&DataKey::Treasury, &(current_treasury + treasury_amount));
+        let count = next_prediction_id(&env);
+        take_creation_payment(&env, &config, &creator, &token);
 
         // Create prediction
         let prediction = Prediction {
             id: count,
             creator: creator.clone(),
             question,
-            unlock_time,
-            yes_pool: if initial_choice { MIN_STAKE } else { 0 },
-            no_pool: if !initial_choice { MIN_STAKE } else { 0 },
+            token,
+            close_time,
+            resolve_after,
+            resolve_deadline: resolve_after + config.resolution_window,
+            pause_offset: paused_total(&env, Operation::Resolve),
+            yes_pool: if initial_choice { config.min_stake } else { 0 },
+            no_pool: if !initial_choice { config.min_stake } else { 0 },
+            forfeited: 0,
             status: PredictionStatus::Open,
-            winner: None,
+            winner: Resolution::Pending,
+            settlement: Settlement::default(),
         };
 
         // Store prediction
-        env.storage()
-            .instance()
-            .set(&DataKey::Prediction(count), &prediction);
-        env.storage()
-            .instance()
-            .set(&DataKey::PredictionCount, &count);
+        storage::set_prediction(&env, &prediction);
 
         // Store creator's stake
         let stake = Stake {
             user: creator,
             choice: initial_choice,
-            amount: MIN_STAKE,
+            amount: config.min_stake,
             claimed: false,
         };
-        env.storage()
-            .instance()
-            .set(&DataKey::UserStake(count, stake.user.clone()), &stake);
+        storage::set_stake(&env, count, &stake);
+        storage::add_user_position(&env, &stake.user, count, MarketKind::Binary);
+        record_increment(&env, count, &stake.user, stake.amount)?;
 
-        count
+        PredictionCreated {
+            prediction_id: count,
+            creator: stake.user,
+            question: prediction.question,
+            token: prediction.token,
+            close_time: prediction.close_time,
+            resolve_after: prediction.resolve_after,
+            resolve_deadline: prediction.resolve_deadline,
+            choice: initial_choice,
+            amount: config.min_stake,
+            creation_fee: config.creation_fee,
+            yes_pool: prediction.yes_pool,
+            no_pool: prediction.no_pool,
+        }
+        .publish(&env);
+
+        Ok(count)
     }
 
-    /// Stake on a prediction
+    /// Stake on a prediction. Staking again adds to the existing stake,
+    /// which must be on the same side: a user backs one side per
+    /// prediction and cannot switch or hedge. Each addition is recorded in
+    /// the stake's history.
     pub fn stake(
         env: Env,
         prediction_id: u64,
@@ -148,45 +669,40 @@ impl TimeLockContract {
         choice: bool,
         amount: i128,
         token: Address,
-    ) {
+    ) -> Result<(), Error> {
         user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
 
-        // Check if user already staked
-        if env
-            .storage()
-            .instance()
-            .has(&DataKey::UserStake(prediction_id, user.clone()))
-        {
-            panic!("Already staked on this prediction");
-        }
-
-        // Validate amount
-        if amount < MIN_STAKE {
-            panic!("Minimum stake is 100 XLM");
-        }
-
-        // Get prediction
-        let mut prediction: Prediction = env
-            .storage()
-            .instance()
-            .get(&DataKey::Prediction(prediction_id))
-            .expect("Prediction not found");
+        let mut prediction = load_stakeable(&env, prediction_id, amount)?;
 
-        // Check status
-        match prediction.status {
-            PredictionStatus::Open => {}
-            _ => panic!("Prediction is not open for staking"),
+        // Stakes must be in the token the prediction was created with
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
         }
 
-        // Check time
-        let current_time = env.ledger().timestamp();
-        if current_time >= prediction.unlock_time {
-            panic!("Prediction has expired");
-        }
+        let mut stake = match storage::get_stake(&env, prediction_id, &user) {
+            Some(stake) if stake.choice != choice => return Err(Error::CannotSwitchSides),
+            Some(stake) if stake.claimed => return Err(Error::AlreadyClaimed),
+            Some(stake) => stake,
+            None => {
+                // A user who withdrew and stakes again is already indexed
+                if storage::get_stake_history(&env, prediction_id, &user).is_empty() {
+                    storage::add_user_position(&env, &user, prediction_id, MarketKind::Binary);
+                }
+                Stake {
+                    user: user.clone(),
+                    choice,
+                    amount: 0,
+                    claimed: false,
+                }
+            }
+        };
+        record_increment(&env, prediction_id, &user, amount)?;
 
         // Transfer tokens
         let token_client = token::Client::new(&env, &token);
-        token_client.transfer(&user, &env.current_contract_address(), &amount);
+        token_client.transfer(&user, env.current_contract_address(), &amount);
 
         // Update pools
         if choice {
@@ -196,145 +712,261 @@ impl TimeLockContract {
         }
 
         // Store updated prediction
-        env.storage()
-            .instance()
-            .set(&DataKey::Prediction(prediction_id), &prediction);
+        storage::set_prediction(&env, &prediction);
 
         // Store user stake
-        let stake = Stake {
-            user: user.clone(),
+        stake.amount += amount;
+        storage::set_stake(&env, prediction_id, &stake);
+
+        Staked {
+            prediction_id,
+            user,
             choice,
             amount,
-            claimed: false,
-        };
-        env.storage()
-            .instance()
-            .set(&DataKey::UserStake(prediction_id, user), &stake);
+            total: stake.amount,
+            yes_pool: prediction.yes_pool,
+            no_pool: prediction.no_pool,
+        }
+        .publish(&env);
+
+        Ok(())
     }
 
-    /// Resolve a prediction (admin only)
-    pub fn resolve(env: Env, admin: Address, prediction_id: u64, winner_choice: bool) {
-        admin.require_auth();
+    /// Close staking on a prediction once its close time has passed so it
+    /// can be resolved. Anyone can call this, e.g. a keeper.
+    ///
+    /// Only binary predictions have separate close and resolve times; the
+    /// other market types close staking and open resolution at a single
+    /// `unlock_time` and are never `Locked`.
+    pub fn lock(env: Env, prediction_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&env);
 
-        // Verify admin
-        let stored_admin: Address = env
-            .storage()
-            .instance()
-            .get(&DataKey::Admin)
-            .expect("Not initialized");
-        if admin != stored_admin {
-            panic!("Only admin can resolve");
+        let mut prediction =
+            storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        if prediction.status != PredictionStatus::Open {
+            return Err(Error::PredictionNotOpen);
+        }
+        if env.ledger().timestamp() < prediction.close_time {
+            return Err(Error::LockTooEarly);
         }
 
-        // Get prediction
-        let mut prediction: Prediction = env
-            .storage()
-            .instance()
-            .get(&DataKey::Prediction(prediction_id))
-            .expect("Prediction not found");
+        prediction.status = PredictionStatus::Locked;
+        storage::set_prediction(&env, &prediction);
 
-        // Check time
-        let current_time = env.ledger().timestamp();
-        if current_time < prediction.unlock_time {
-            panic!("Cannot resolve before unlock time");
+        PredictionLocked {
+            prediction_id,
+            yes_pool: prediction.yes_pool,
+            no_pool: prediction.no_pool,
         }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Resolve a prediction (resolver only)
+    ///
+    /// If either side has no stakers there is nobody to pay or nobody to pay
+    /// from (e.g. only the creator's initial stake exists), so the prediction
+    /// is resolved as invalid instead and every staker gets their stake back.
+    pub fn resolve(
+        env: Env,
+        resolver: Address,
+        prediction_id: u64,
+        winner_choice: bool,
+    ) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
 
-        // Check status
-        match prediction.status {
-            PredictionStatus::Open => {}
-            _ => panic!("Prediction already resolved"),
+        let mut prediction = load_resolvable(&env, prediction_id)?;
+
+        if prediction.yes_pool == 0 || prediction.no_pool == 0 {
+            resolve_as_invalid(&env, resolver, prediction);
+            return Ok(());
         }
 
         // Update prediction
         prediction.status = PredictionStatus::Resolved;
-        prediction.winner = Some(winner_choice);
+        let winning_pool = if winner_choice {
+            prediction.winner = Resolution::Yes;
+            prediction.yes_pool
+        } else {
+            prediction.winner = Resolution::No;
+            prediction.no_pool
+        };
+        prediction.settlement = Settlement::winners(
+            &env,
+            &prediction.token,
+            prediction.yes_pool + prediction.no_pool + prediction.forfeited,
+            winning_pool,
+        );
 
-        env.storage()
-            .instance()
-            .set(&DataKey::Prediction(prediction_id), &prediction);
+        storage::set_prediction(&env, &prediction);
+
+        Resolved {
+            prediction_id,
+            resolver,
+            winner: winner_choice,
+            yes_pool: prediction.yes_pool,
+            no_pool: prediction.no_pool,
+            platform_fee: prediction.settlement.platform_fee,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Resolve a prediction as invalid when the question cannot be answered
+    /// (resolver only). Every staker can then claim back their own stake.
+    pub fn resolve_invalid(env: Env, resolver: Address, prediction_id: u64) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
+
+        let prediction = load_resolvable(&env, prediction_id)?;
+        resolve_as_invalid(&env, resolver, prediction);
+
+        Ok(())
     }
 
     /// Claim winnings
-    pub fn claim(env: Env, prediction_id: u64, user: Address, token: Address) -> i128 {
+    pub fn claim(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
         user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
 
-        // Get prediction
-        let prediction: Prediction = env
-            .storage()
-            .instance()
-            .get(&DataKey::Prediction(prediction_id))
-            .expect("Prediction not found");
+        // Calculate payout
+        let (prediction, stake, user_payout) = load_claim(&env, prediction_id, &user)?;
 
-        // Check if resolved
-        let winner_choice = match prediction.winner {
-            Some(w) => w,
-            None => panic!("Prediction not resolved yet"),
-        };
+        // Payouts are always in the prediction's own token
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
 
-        // Get user stake
-        let mut stake: Stake = env
-            .storage()
-            .instance()
-            .get(&DataKey::UserStake(prediction_id, user.clone()))
-            .expect("No stake found");
+        settle_claim(&env, &user, prediction, stake, user_payout);
 
-        // Check if already claimed
-        if stake.claimed {
-            panic!("Already claimed");
+        Ok(user_payout)
+    }
+
+    /// Cancel an unresolved prediction so every staker can take their stake
+    /// back with `refund`. A resolver can cancel any unresolved prediction;
+    /// the creator can cancel only while nobody else has staked.
+    ///
+    /// The creation fee is not refunded and stays in the treasury.
+    pub fn cancel_prediction(env: Env, caller: Address, prediction_id: u64) -> Result<(), Error> {
+        caller.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        require_unsettled(&prediction.status)?;
+
+        if !has_role(&env, Role::Resolver, &caller)? {
+            if caller != prediction.creator {
+                return Err(Error::Unauthorized);
+            }
+            // Only the creator's initial stake may be in the pools, and it
+            // may already have been withdrawn
+            let creator_stake =
+                storage::get_stake(&env, prediction_id, &caller).map_or(0, |stake| stake.amount);
+            if prediction.yes_pool + prediction.no_pool != creator_stake {
+                return Err(Error::CannotCancel);
+            }
         }
 
-        // Check if user won
-        if stake.choice != winner_choice {
-            panic!("You lost this prediction");
+        prediction.status = PredictionStatus::Cancelled;
+        release_forfeited(&env, &prediction);
+        storage::set_prediction(&env, &prediction);
+
+        Cancelled {
+            prediction_id,
+            caller,
+            yes_pool: prediction.yes_pool,
+            no_pool: prediction.no_pool,
         }
+        .publish(&env);
 
-        // Calculate payout
-        let total_pool = prediction.yes_pool + prediction.no_pool;
-        let winning_pool = if winner_choice {
-            prediction.yes_pool
-        } else {
-            prediction.no_pool
-        };
+        Ok(())
+    }
 
-        // Take 5% platform fee
-        let prize_pool = (total_pool * 95) / 100;
-        let platform_fee = total_pool - prize_pool;
+    /// Mark a prediction that was not resolved by its deadline as expired
+    /// so every staker can take their stake back with `refund`. Anyone can
+    /// call this. The deadline is extended by the time resolution was
+    /// paused while the prediction was live.
+    pub fn expire(env: Env, prediction_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&env);
 
-        // Update treasury
-        let current_treasury: i128 = env.storage().instance().get(&DataKey::Treasury).unwrap_or(0);
-        env.storage()
-            .instance()
-            .set(&DataKey::Treasury, &(current_treasury + platform_fee));
+        let mut prediction =
+            storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
 
-        // Calculate user's share: (user_stake / winning_pool) * prize_pool
-        let user_payout = (stake.amount * prize_pool) / winning_pool;
+        require_unsettled(&prediction.status)?;
 
-        // Mark as claimed
-        stake.claimed = true;
-        env.storage()
-            .instance()
-            .set(&DataKey::UserStake(prediction_id, user.clone()), &stake);
+        let deadline =
+            extended_deadline(&env, prediction.resolve_deadline, prediction.pause_offset);
+        if env.ledger().timestamp() < deadline {
+            return Err(Error::DeadlineNotReached);
+        }
 
-        // Transfer winnings
-        let token_client = token::Client::new(&env, &token);
-        token_client.transfer(&env.current_contract_address(), &user, &user_payout);
+        prediction.status = PredictionStatus::Expired;
+        release_forfeited(&env, &prediction);
+        storage::set_prediction(&env, &prediction);
+
+        Expired {
+            prediction_id,
+            yes_pool: prediction.yes_pool,
+            no_pool: prediction.no_pool,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Take back the full stake from a cancelled or expired prediction
+    pub fn refund(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let prediction =
+            storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        require_refundable(&prediction.status)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
 
-        user_payout
+        refund_stake(&env, &prediction, &user)
     }
 
     /// Get prediction details
-    pub fn get_prediction(env: Env, prediction_id: u64) -> Prediction {
-        env.storage()
-            .instance()
-            .get(&DataKey::Prediction(prediction_id))
-            .expect("Prediction not found")
+    pub fn get_prediction(env: Env, prediction_id: u64) -> Result<Prediction, Error> {
+        storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)
     }
 
     /// Get user stake
     pub fn get_stake(env: Env, prediction_id: u64, user: Address) -> Option<Stake> {
-        env.storage()
-            .instance()
-            .get(&DataKey::UserStake(prediction_id, user))
+        storage::get_stake(&env, prediction_id, &user)
+    }
+
+    /// Get the additions that make up a user's stake, oldest first. Stakes
+    /// placed before histories were recorded start at their first top-up.
+    pub fn get_stake_history(env: Env, prediction_id: u64, user: Address) -> Vec<StakeIncrement> {
+        storage::get_stake_history(&env, prediction_id, &user)
     }
 
     /// Get total prediction count
@@ -345,35 +977,71 @@ impl TimeLockContract {
             .unwrap_or(0)
     }
 
-    /// Get treasury balance
-    pub fn get_treasury(env: Env) -> i128 {
-        env.storage().instance().get(&DataKey::Treasury).unwrap_or(0)
+    /// Get treasury balance for a token
+    pub fn get_treasury(env: Env, token: Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::Treasury(token))
+            .unwrap_or(0)
     }
 
-    /// Withdraw treasury (admin only)
-    pub fn withdraw_treasury(env: Env, admin: Address, amount: i128, token: Address) {
-        admin.require_auth();
+    /// Withdraw treasury for a token to the treasurer (treasurer only)
+    pub fn withdraw_treasury(
+        env: Env,
+        treasurer: Address,
+        amount: i128,
+        token: Address,
+    ) -> Result<(), Error> {
+        treasurer.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Withdraw)?;
+        require_role(&env, Role::Treasurer, &treasurer)?;
 
-        let stored_admin: Address = env
-            .storage()
-            .instance()
-            .get(&DataKey::Admin)
-            .expect("Not initialized");
-        if admin != stored_admin {
-            panic!("Only admin can withdraw");
+        if amount <= 0 {
+            return Err(Error::InvalidAmount);
         }
 
-        let current_treasury: i128 = env.storage().instance().get(&DataKey::Treasury).unwrap_or(0);
+        let current_treasury: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::Treasury(token.clone()))
+            .unwrap_or(0);
         if amount > current_treasury {
-            panic!("Insufficient treasury balance");
+            return Err(Error::InsufficientTreasury);
         }
 
-        env.storage()
-            .instance()
-            .set(&DataKey::Treasury, &(current_treasury - amount));
+        env.storage().instance().set(
+            &DataKey::Treasury(token.clone()),
+            &(current_treasury - amount),
+        );
 
         let token_client = token::Client::new(&env, &token);
-        token_client.transfer(&env.current_contract_address(), &admin, &amount);
+        token_client.transfer(&env.current_contract_address(), &treasurer, &amount);
+
+        TreasuryWithdrawn {
+            token,
+            treasurer,
+            amount,
+            remaining: current_treasury - amount,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Extend the storage TTL of a prediction so it is not archived.
+    /// Anyone can call this, e.g. a keeper for long-running markets.
+    pub fn bump_prediction(env: Env, prediction_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&env);
+
+        if !storage::extend_prediction(&env, prediction_id) {
+            // Not in persistent storage yet, move it there
+            let prediction =
+                storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+            storage::set_prediction(&env, &prediction);
+        }
+
+        Ok(())
     }
 }
 
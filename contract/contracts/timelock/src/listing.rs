@@ -0,0 +1,74 @@
+//! Paginated market listing.
+//!
+//! Pages walk a window of prediction ids rather than counting matches, so a
+//! page always costs at most `MAX_PAGE_SIZE` reads no matter how sparse the
+//! filter is. Ids are shared by every market type and only binary
+//! predictions are listed, so a page can hold fewer entries than `limit`
+//! even without a filter. The next page starts at `start + limit` and the
+//! last id is `get_prediction_count()`.
+
+use soroban_sdk::{contractimpl, contracttype, Env, Vec};
+
+use crate::{
+    storage, Prediction, PredictionStatus, TimeLockContract, TimeLockContractArgs,
+    TimeLockContractClient,
+};
+
+pub const MAX_PAGE_SIZE: u32 = 50;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+#[repr(u32)]
+pub enum StatusFilter {
+    All = 0,
+    Open = 1,               // Still accepting stakes
+    AwaitingResolution = 2, // Locked, or past its close time, but not resolved yet
+    Resolved = 3,
+    Cancelled = 4,
+    Expired = 5,
+}
+
+impl StatusFilter {
+    fn matches(&self, env: &Env, prediction: &Prediction) -> bool {
+        let closed = env.ledger().timestamp() >= prediction.close_time;
+        match self {
+            StatusFilter::All => true,
+            StatusFilter::Open => prediction.status == PredictionStatus::Open && !closed,
+            StatusFilter::AwaitingResolution => match prediction.status {
+                PredictionStatus::Open => closed,
+                PredictionStatus::Locked => true,
+                _ => false,
+            },
+            StatusFilter::Resolved => prediction.status == PredictionStatus::Resolved,
+            StatusFilter::Cancelled => prediction.status == PredictionStatus::Cancelled,
+            StatusFilter::Expired => prediction.status == PredictionStatus::Expired,
+        }
+    }
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// List the binary predictions with ids `start..start + limit` that
+    /// match `status_filter`. `limit` is capped at `MAX_PAGE_SIZE`.
+    pub fn list_predictions(
+        env: Env,
+        start: u64,
+        limit: u32,
+        status_filter: StatusFilter,
+    ) -> Vec<Prediction> {
+        let count: u64 = Self::get_prediction_count(env.clone());
+        let end = start
+            .saturating_add(limit.min(MAX_PAGE_SIZE) as u64)
+            .min(count.saturating_add(1));
+
+        let mut page = Vec::new(&env);
+        for prediction_id in start.max(1)..end {
+            if let Some(prediction) = storage::get_prediction(&env, prediction_id) {
+                if status_filter.matches(&env, &prediction) {
+                    page.push_back(prediction);
+                }
+            }
+        }
+        page
+    }
+}
@@ -0,0 +1,663 @@
+//! LMSR (logarithmic market scoring rule) predictions.
+//!
+//! Instead of pooling stakes, an LMSR prediction is an automated market
+//! maker selling YES and NO shares. Each winning share pays out one base unit
+//! of the token on resolution; losing shares pay nothing. The price of a
+//! trade is the change in the cost function
+//!
+//! `C(q_yes, q_no) = b * ln(e^(q_yes / b) + e^(q_no / b))`
+//!
+//! where `b` is the liquidity parameter chosen by the creator. A larger `b`
+//! moves prices less per share but risks more of the creator's subsidy: the
+//! market maker can lose at most `b * ln 2`, which the creator pays in up
+//! front. Whatever is left after reserving every winning share goes back to
+//! the creator on resolution.
+//!
+//! All math is deterministic fixed point (see `fixed`). Buys round the cost
+//! up and sells round the proceeds down, so rounding always favours the
+//! market and it stays solvent. The platform fee is charged on top of each
+//! buy and credited to the treasury.
+//!
+//! A market that is cancelled or expires instead of being resolved settles
+//! at its final prices: each YES share redeems for the YES price and each NO
+//! share for the NO price, and the creator gets back what is left. The cost
+//! function never falls below the value of the shares at its prices, so the
+//! market can always cover this. Once resolution has been paused for
+//! `EMERGENCY_DELAY`, traders can sell their shares back to the market with
+//! `emergency_withdraw_lmsr` even after trading has closed, and the creator
+//! can then cancel to take back the subsidy.
+
+use soroban_sdk::{contractimpl, contracttype, token, Address, Env, String};
+
+use crate::fixed::{exp_neg, ln_1p, LN_2, ONE};
+use crate::{
+    add_to_treasury, extended_deadline, has_role, next_prediction_id, paused_total,
+    require_not_paused, require_role, require_trading, require_unsettled, storage, validate_market,
+    Error, LmsrCancelled, LmsrCreated, LmsrExpired, LmsrResolved, MarketKind, Operation,
+    PredictionStatus, Resolution, Role, SharesBought, SharesRedeemed, SharesSold, TimeLockContract,
+    TimeLockContractArgs, TimeLockContractClient,
+};
+
+// Keeps every intermediate of the cost function inside i128
+const MAX_LIQUIDITY: i128 = 1_000_000_000_000_000;
+const MAX_SHARES: i128 = 1_000_000_000_000_000_000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LmsrPrediction {
+    pub id: u64,
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64,
+    pub pause_offset: u64, // Resolve pause time before creation
+    pub liquidity: i128,   // The liquidity parameter `b`
+    pub subsidy: i128,     // Paid in by the creator, covers the worst-case loss
+    pub yes_shares: i128,
+    pub no_shares: i128,
+    pub collected: i128, // Net paid in by traders, excluding fees
+    pub status: PredictionStatus,
+    pub winner: Resolution,
+    pub creator_return: i128, // Paid to the creator once resolved, cancelled or expired
+}
+
+impl LmsrPrediction {
+    /// The cost function at the given share totals, scaled by `ONE`.
+    fn cost(&self, yes_shares: i128, no_shares: i128) -> i128 {
+        let b = self.liquidity;
+        let spread = (yes_shares - no_shares).abs() * ONE / b;
+        yes_shares.max(no_shares) * ONE + b * ln_1p(exp_neg(spread))
+    }
+
+    /// Share totals after adding `shares` (which may be negative) to `outcome`.
+    fn shares_after(&self, outcome: bool, shares: i128) -> (i128, i128) {
+        if outcome {
+            (self.yes_shares + shares, self.no_shares)
+        } else {
+            (self.yes_shares, self.no_shares + shares)
+        }
+    }
+
+    /// What buying `shares` of `outcome` costs, before the platform fee.
+    fn buy_cost(&self, outcome: bool, shares: i128) -> Result<i128, Error> {
+        if shares > MAX_SHARES {
+            return Err(Error::InvalidShares);
+        }
+        let (yes_shares, no_shares) = self.shares_after(outcome, shares);
+        if yes_shares > MAX_SHARES || no_shares > MAX_SHARES {
+            return Err(Error::InvalidShares);
+        }
+        let diff = self.cost(yes_shares, no_shares) - self.cost(self.yes_shares, self.no_shares);
+        // Round up, plus a unit to cover the error of the series
+        Ok((diff + ONE - 1) / ONE + 1)
+    }
+
+    /// Apply a sale of `shares` of `outcome`. Returns the proceeds.
+    fn sell(&mut self, outcome: bool, shares: i128) -> i128 {
+        let proceeds = self.sell_proceeds(outcome, shares);
+        (self.yes_shares, self.no_shares) = self.shares_after(outcome, -shares);
+        self.collected -= proceeds;
+        proceeds
+    }
+
+    /// Sell every share in `position` back to the market. Returns the
+    /// proceeds.
+    pub(crate) fn sell_back(&mut self, position: &mut LmsrPosition) -> i128 {
+        let mut proceeds = 0;
+        for outcome in [true, false] {
+            let shares = position.shares(outcome);
+            if shares > 0 {
+                proceeds += self.sell(outcome, shares);
+                position.add_shares(outcome, -shares);
+            }
+        }
+        proceeds
+    }
+
+    /// What selling `shares` of `outcome` back to the market pays.
+    fn sell_proceeds(&self, outcome: bool, shares: i128) -> i128 {
+        let (yes_shares, no_shares) = self.shares_after(outcome, -shares);
+        let diff = self.cost(self.yes_shares, self.no_shares) - self.cost(yes_shares, no_shares);
+        // Round down, minus a unit to cover the error of the series
+        (diff / ONE - 1).max(0)
+    }
+
+    /// The current (YES, NO) prices scaled by `ONE`. They always sum to `ONE`.
+    fn prices(&self) -> (i128, i128) {
+        let spread = (self.yes_shares - self.no_shares).abs() * ONE / self.liquidity;
+        let tail = exp_neg(spread);
+        let yes_price = if self.yes_shares >= self.no_shares {
+            ONE * ONE / (ONE + tail)
+        } else {
+            tail * ONE / (ONE + tail)
+        };
+        (yes_price, ONE - yes_price)
+    }
+
+    /// The value of the given shares at the current prices, scaled by `ONE`.
+    fn value(&self, yes_shares: i128, no_shares: i128) -> i128 {
+        let (yes_price, no_price) = self.prices();
+        yes_shares * yes_price + no_shares * no_price
+    }
+
+    /// What `position` redeems for: its winning shares once resolved, or
+    /// every share at the final prices once cancelled or expired.
+    pub(crate) fn payout_for(&self, position: &LmsrPosition) -> Result<i128, Error> {
+        if position.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let payout = match (&self.status, &self.winner) {
+            (PredictionStatus::Resolved, Resolution::Yes) => position.yes_shares,
+            (PredictionStatus::Resolved, Resolution::No) => position.no_shares,
+            (PredictionStatus::Cancelled | PredictionStatus::Expired, _) => {
+                // Round down
+                self.value(position.yes_shares, position.no_shares) / ONE
+            }
+            _ => return Err(Error::NotResolved),
+        };
+
+        if payout == 0 {
+            return Err(Error::LostPrediction);
+        }
+        Ok(payout)
+    }
+
+    /// End the market without a winner. Every share is reserved at the
+    /// final prices, rounded up, and the rest is left for the creator.
+    fn void(&mut self, status: PredictionStatus) {
+        let reserved = (self.value(self.yes_shares, self.no_shares) + ONE - 1) / ONE;
+        self.status = status;
+        self.creator_return = (self.subsidy + self.collected - reserved).max(0);
+    }
+}
+
+/// Send the creator what is left of a settled LMSR prediction.
+fn pay_creator(env: &Env, prediction: &LmsrPrediction) {
+    if prediction.creator_return > 0 {
+        let token_client = token::Client::new(env, &prediction.token);
+        token_client.transfer(
+            &env.current_contract_address(),
+            &prediction.creator,
+            &prediction.creator_return,
+        );
+    }
+}
+
+/// Redeem a user's shares in a resolved, cancelled or expired LMSR
+/// prediction.
+pub(crate) fn settle_lmsr_claim(
+    env: &Env,
+    prediction: &LmsrPrediction,
+    user: &Address,
+) -> Result<i128, Error> {
+    let mut position =
+        storage::get_lmsr_position(env, prediction.id, user).ok_or(Error::StakeNotFound)?;
+    let payout = prediction.payout_for(&position)?;
+
+    position.claimed = true;
+    storage::set_lmsr_position(env, prediction.id, &position);
+
+    let token_client = token::Client::new(env, &prediction.token);
+    token_client.transfer(&env.current_contract_address(), user, &payout);
+
+    SharesRedeemed {
+        prediction_id: prediction.id,
+        user: user.clone(),
+        payout,
+    }
+    .publish(env);
+
+    Ok(payout)
+}
+
+/// The creator's subsidy for liquidity `b`: `b * ln 2`, rounded up.
+fn lmsr_subsidy(liquidity: i128) -> i128 {
+    (liquidity * LN_2 + ONE - 1) / ONE + 1
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LmsrPosition {
+    pub user: Address,
+    pub yes_shares: i128,
+    pub no_shares: i128,
+    pub claimed: bool,
+}
+
+impl LmsrPosition {
+    pub(crate) fn shares(&self, outcome: bool) -> i128 {
+        if outcome {
+            self.yes_shares
+        } else {
+            self.no_shares
+        }
+    }
+
+    fn add_shares(&mut self, outcome: bool, shares: i128) {
+        if outcome {
+            self.yes_shares += shares;
+        } else {
+            self.no_shares += shares;
+        }
+    }
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Create an LMSR prediction with liquidity parameter `liquidity`. The
+    /// creator pays the creation fee plus a subsidy of `liquidity * ln 2`.
+    pub fn create_lmsr(
+        env: Env,
+        creator: Address,
+        question: String,
+        unlock_time: u64,
+        liquidity: i128,
+        token: Address,
+    ) -> Result<u64, Error> {
+        creator.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Create)?;
+
+        // Validate inputs
+        let config = storage::get_config(&env);
+        validate_market(&env, &config, &question, unlock_time)?;
+
+        if liquidity < config.min_stake || liquidity > MAX_LIQUIDITY {
+            return Err(Error::InvalidLiquidity);
+        }
+
+        let count = next_prediction_id(&env);
+        let subsidy = lmsr_subsidy(liquidity);
+
+        // Transfer creation fee + subsidy from creator
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(
+            &creator,
+            env.current_contract_address(),
+            &(config.creation_fee + subsidy),
+        );
+        add_to_treasury(&env, &token, config.treasury_share());
+
+        let prediction = LmsrPrediction {
+            id: count,
+            creator,
+            question,
+            token,
+            unlock_time,
+            resolve_deadline: unlock_time + config.resolution_window,
+            pause_offset: paused_total(&env, Operation::Resolve),
+            liquidity,
+            subsidy,
+            yes_shares: 0,
+            no_shares: 0,
+            collected: 0,
+            status: PredictionStatus::Open,
+            winner: Resolution::Pending,
+            creator_return: 0,
+        };
+        storage::set_lmsr(&env, &prediction);
+        storage::add_user_position(&env, &prediction.creator, count, MarketKind::Lmsr);
+
+        LmsrCreated {
+            prediction_id: count,
+            creator: prediction.creator,
+            question: prediction.question,
+            token: prediction.token,
+            unlock_time,
+            resolve_deadline: prediction.resolve_deadline,
+            liquidity,
+            subsidy,
+            creation_fee: config.creation_fee,
+        }
+        .publish(&env);
+
+        Ok(count)
+    }
+
+    /// Buy `shares` of an outcome (`true` for YES). Fails with
+    /// `SlippageExceeded` if the cost, fee included, is above `max_cost`,
+    /// and with `AlreadyClaimed` once the user has left the market through
+    /// `emergency_withdraw_lmsr`. Returns what was paid.
+    pub fn buy_shares(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        outcome: bool,
+        shares: i128,
+        max_cost: i128,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        if shares <= 0 {
+            return Err(Error::InvalidShares);
+        }
+
+        let mut prediction =
+            storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_trading(
+            &env,
+            &prediction.status,
+            prediction.unlock_time,
+            &prediction.token,
+            &token,
+        )?;
+
+        // A user's first trade in the market adds it to their index, unless
+        // they created it
+        let mut position = match storage::get_lmsr_position(&env, prediction_id, &user) {
+            Some(position) => position,
+            None => {
+                if user != prediction.creator {
+                    storage::add_user_position(&env, &user, prediction_id, MarketKind::Lmsr);
+                }
+                LmsrPosition {
+                    user: user.clone(),
+                    yes_shares: 0,
+                    no_shares: 0,
+                    claimed: false,
+                }
+            }
+        };
+        // A position sold back through the emergency exit is closed for good
+        if position.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let cost = prediction.buy_cost(outcome, shares)?;
+        let (_, fee) = storage::get_config(&env).split_pool(cost);
+        if cost + fee > max_cost {
+            return Err(Error::SlippageExceeded);
+        }
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&user, env.current_contract_address(), &(cost + fee));
+        add_to_treasury(&env, &token, fee);
+
+        (prediction.yes_shares, prediction.no_shares) = prediction.shares_after(outcome, shares);
+        prediction.collected += cost;
+        storage::set_lmsr(&env, &prediction);
+
+        position.add_shares(outcome, shares);
+        storage::set_lmsr_position(&env, prediction_id, &position);
+
+        SharesBought {
+            prediction_id,
+            user,
+            outcome,
+            shares,
+            cost,
+            fee,
+            yes_price: prediction.prices().0,
+        }
+        .publish(&env);
+
+        Ok(cost + fee)
+    }
+
+    /// Sell `shares` of an outcome back to the market. Fails with
+    /// `SlippageExceeded` if the proceeds are below `min_proceeds`. Returns
+    /// what was received.
+    pub fn sell_shares(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        outcome: bool,
+        shares: i128,
+        min_proceeds: i128,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        if shares <= 0 {
+            return Err(Error::InvalidShares);
+        }
+
+        let mut prediction =
+            storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_trading(
+            &env,
+            &prediction.status,
+            prediction.unlock_time,
+            &prediction.token,
+            &token,
+        )?;
+
+        let mut position =
+            storage::get_lmsr_position(&env, prediction_id, &user).ok_or(Error::StakeNotFound)?;
+        if position.shares(outcome) < shares {
+            return Err(Error::InsufficientShares);
+        }
+
+        let proceeds = prediction.sell(outcome, shares);
+        if proceeds < min_proceeds {
+            return Err(Error::SlippageExceeded);
+        }
+        storage::set_lmsr(&env, &prediction);
+
+        position.add_shares(outcome, -shares);
+        storage::set_lmsr_position(&env, prediction_id, &position);
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&env.current_contract_address(), &user, &proceeds);
+
+        SharesSold {
+            prediction_id,
+            user,
+            outcome,
+            shares,
+            proceeds,
+            yes_price: prediction.prices().0,
+        }
+        .publish(&env);
+
+        Ok(proceeds)
+    }
+
+    /// Quote what `buy_shares` would charge right now, fee included
+    pub fn quote_buy_shares(
+        env: Env,
+        prediction_id: u64,
+        outcome: bool,
+        shares: i128,
+    ) -> Result<i128, Error> {
+        if shares <= 0 {
+            return Err(Error::InvalidShares);
+        }
+        let prediction = storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        let cost = prediction.buy_cost(outcome, shares)?;
+        let (_, fee) = storage::get_config(&env).split_pool(cost);
+        Ok(cost + fee)
+    }
+
+    /// Quote what `sell_shares` would pay right now
+    pub fn quote_sell_shares(
+        env: Env,
+        prediction_id: u64,
+        outcome: bool,
+        shares: i128,
+    ) -> Result<i128, Error> {
+        let prediction = storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        let outstanding = if outcome {
+            prediction.yes_shares
+        } else {
+            prediction.no_shares
+        };
+        if shares <= 0 || shares > outstanding {
+            return Err(Error::InvalidShares);
+        }
+        Ok(prediction.sell_proceeds(outcome, shares))
+    }
+
+    /// Get the current (YES, NO) prices of an LMSR prediction, scaled by
+    /// 10^18 so they sum to 10^18
+    pub fn get_lmsr_prices(env: Env, prediction_id: u64) -> Result<(i128, i128), Error> {
+        let prediction = storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        Ok(prediction.prices())
+    }
+
+    /// Resolve an LMSR prediction (resolver only). The subsidy and trading
+    /// proceeds left after reserving every winning share go to the creator.
+    pub fn resolve_lmsr(
+        env: Env,
+        resolver: Address,
+        prediction_id: u64,
+        outcome: bool,
+    ) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
+
+        let mut prediction =
+            storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        require_unsettled(&prediction.status)?;
+
+        let current_time = env.ledger().timestamp();
+        if current_time < prediction.unlock_time {
+            return Err(Error::ResolveTooEarly);
+        }
+        if current_time
+            >= extended_deadline(&env, prediction.resolve_deadline, prediction.pause_offset)
+        {
+            return Err(Error::ResolutionExpired);
+        }
+
+        let winning_shares = if outcome {
+            prediction.yes_shares
+        } else {
+            prediction.no_shares
+        };
+
+        prediction.status = PredictionStatus::Resolved;
+        prediction.winner = if outcome {
+            Resolution::Yes
+        } else {
+            Resolution::No
+        };
+        prediction.creator_return = prediction.subsidy + prediction.collected - winning_shares;
+        storage::set_lmsr(&env, &prediction);
+        pay_creator(&env, &prediction);
+
+        LmsrResolved {
+            prediction_id,
+            resolver,
+            outcome,
+            yes_shares: prediction.yes_shares,
+            no_shares: prediction.no_shares,
+            creator_return: prediction.creator_return,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Cancel an unresolved LMSR prediction (resolver, or creator while
+    /// nobody else holds shares). Shares then redeem at the final prices
+    /// through `claim_lmsr` and the rest goes back to the creator.
+    pub fn cancel_lmsr(env: Env, caller: Address, prediction_id: u64) -> Result<(), Error> {
+        caller.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        if !has_role(&env, Role::Resolver, &caller)? {
+            if caller != prediction.creator {
+                return Err(Error::Unauthorized);
+            }
+            // Only the creator's own shares may be outstanding
+            let (yes_shares, no_shares) = storage::get_lmsr_position(&env, prediction_id, &caller)
+                .map_or((0, 0), |position| (position.yes_shares, position.no_shares));
+            if prediction.yes_shares != yes_shares || prediction.no_shares != no_shares {
+                return Err(Error::CannotCancel);
+            }
+        }
+
+        prediction.void(PredictionStatus::Cancelled);
+        storage::set_lmsr(&env, &prediction);
+        pay_creator(&env, &prediction);
+
+        LmsrCancelled {
+            prediction_id,
+            caller,
+            yes_shares: prediction.yes_shares,
+            no_shares: prediction.no_shares,
+            creator_return: prediction.creator_return,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Mark an LMSR prediction that was not resolved by its deadline as
+    /// expired. Shares then redeem at the final prices through `claim_lmsr`
+    /// and the rest goes back to the creator. Anyone can call this.
+    pub fn expire_lmsr(env: Env, prediction_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        let deadline =
+            extended_deadline(&env, prediction.resolve_deadline, prediction.pause_offset);
+        if env.ledger().timestamp() < deadline {
+            return Err(Error::DeadlineNotReached);
+        }
+
+        prediction.void(PredictionStatus::Expired);
+        storage::set_lmsr(&env, &prediction);
+        pay_creator(&env, &prediction);
+
+        LmsrExpired {
+            prediction_id,
+            yes_shares: prediction.yes_shares,
+            no_shares: prediction.no_shares,
+            creator_return: prediction.creator_return,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Redeem shares from an LMSR prediction: one base unit of the token per
+    /// winning share once resolved, or every share at the final prices once
+    /// cancelled or expired
+    pub fn claim_lmsr(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let prediction = storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        settle_lmsr_claim(&env, &prediction, &user)
+    }
+
+    /// Get LMSR prediction details
+    pub fn get_lmsr(env: Env, prediction_id: u64) -> Result<LmsrPrediction, Error> {
+        storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)
+    }
+
+    /// Get a user's shares in an LMSR prediction
+    pub fn get_lmsr_position(env: Env, prediction_id: u64, user: Address) -> Option<LmsrPosition> {
+        storage::get_lmsr_position(&env, prediction_id, &user)
+    }
+}
@@ -0,0 +1,281 @@
+//! Emergency pause.
+//!
+//! A pauser can halt each `Operation` independently, e.g. stop claims while
+//! a payout bug is investigated and keep everything else running. If
+//! resolution stays paused for `EMERGENCY_DELAY`, stakers can take their
+//! stakes back out of unresolved markets with the `emergency_withdraw*`
+//! entry points, so funds cannot be locked forever by a pause. LMSR traders
+//! sell their shares back to the market instead, and CPMM predictions can
+//! be cancelled by anyone.
+//!
+//! Resolution deadlines are extended by the time resolution spends paused
+//! while a market is live, so a pause cannot let a market expire before its
+//! resolvers had the full resolution window.
+
+use soroban_sdk::{contractimpl, contracttype, token, Address, Env};
+
+use crate::{
+    require_role, require_unsettled, storage, EmergencyWithdrawn, Error, Paused, PredictionStatus,
+    Role, TimeLockContract, TimeLockContractArgs, TimeLockContractClient, Unpaused,
+};
+
+pub const EMERGENCY_DELAY: u64 = 30 * 24 * 3600; // 30 days in seconds
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+#[repr(u32)]
+pub enum Operation {
+    Create = 0,   // Creating markets of any type
+    Stake = 1,    // Staking on markets of any type
+    Resolve = 2,  // Resolving markets of any type
+    Claim = 3,    // Claims and refunds
+    Withdraw = 4, // Treasury withdrawals
+}
+
+pub(crate) fn require_not_paused(env: &Env, operation: Operation) -> Result<(), Error> {
+    if storage::get_paused_since(env, operation).is_some() {
+        return Err(Error::OperationPaused);
+    }
+    Ok(())
+}
+
+/// Total time `operation` has been paused for, including a pause in progress.
+pub(crate) fn paused_total(env: &Env, operation: Operation) -> u64 {
+    let total = storage::get_paused_total(env, operation);
+    match storage::get_paused_since(env, operation) {
+        Some(since) => total + (env.ledger().timestamp() - since),
+        None => total,
+    }
+}
+
+/// A market's resolution deadline, extended by the time resolution has been
+/// paused since it was created. `pause_offset` is the `paused_total` of
+/// `Operation::Resolve` when the market was created.
+pub(crate) fn extended_deadline(env: &Env, deadline: u64, pause_offset: u64) -> u64 {
+    deadline + paused_total(env, Operation::Resolve).saturating_sub(pause_offset)
+}
+
+/// Whether resolution has been paused for `EMERGENCY_DELAY`.
+pub(crate) fn emergency_open(env: &Env) -> bool {
+    storage::get_paused_since(env, Operation::Resolve)
+        .is_some_and(|since| env.ledger().timestamp() >= since + EMERGENCY_DELAY)
+}
+
+/// Check the emergency exit is open and the market has not been settled.
+fn require_emergency(env: &Env, status: &PredictionStatus) -> Result<(), Error> {
+    if !emergency_open(env) {
+        return Err(Error::EmergencyNotAvailable);
+    }
+
+    require_unsettled(status)
+}
+
+/// Send a withdrawn stake back to its owner.
+fn pay_out_stake(env: &Env, prediction_id: u64, user: Address, token: &Address, amount: i128) {
+    let token_client = token::Client::new(env, token);
+    token_client.transfer(&env.current_contract_address(), &user, &amount);
+
+    EmergencyWithdrawn {
+        prediction_id,
+        user,
+        amount,
+    }
+    .publish(env);
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Halt an operation (pauser only). Pausing an operation that is already
+    /// paused keeps the original pause time.
+    pub fn pause(env: Env, pauser: Address, operation: Operation) -> Result<(), Error> {
+        pauser.require_auth();
+        storage::extend_instance(&env);
+        require_role(&env, Role::Pauser, &pauser)?;
+
+        if storage::get_paused_since(&env, operation).is_none() {
+            storage::set_paused_since(&env, operation, Some(env.ledger().timestamp()));
+        }
+
+        Paused { operation, pauser }.publish(&env);
+
+        Ok(())
+    }
+
+    /// Resume an operation (pauser only)
+    pub fn unpause(env: Env, pauser: Address, operation: Operation) -> Result<(), Error> {
+        pauser.require_auth();
+        storage::extend_instance(&env);
+        require_role(&env, Role::Pauser, &pauser)?;
+
+        if storage::get_paused_since(&env, operation).is_some() {
+            storage::set_paused_total(&env, operation, paused_total(&env, operation));
+            storage::set_paused_since(&env, operation, None);
+        }
+
+        Unpaused { operation, pauser }.publish(&env);
+
+        Ok(())
+    }
+
+    /// Check whether an operation is paused
+    pub fn is_paused(env: Env, operation: Operation) -> bool {
+        storage::get_paused_since(&env, operation).is_some()
+    }
+
+    /// Get the time an operation was paused at, if it is paused
+    pub fn get_paused_since(env: Env, operation: Operation) -> Option<u64> {
+        storage::get_paused_since(&env, operation)
+    }
+
+    /// Take a stake back out of an unresolved prediction once resolution
+    /// has been paused for `EMERGENCY_DELAY`
+    pub fn emergency_withdraw(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_emergency(&env, &prediction.status)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        let mut stake =
+            storage::get_stake(&env, prediction_id, &user).ok_or(Error::StakeNotFound)?;
+        if stake.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        // Leave the pools as if the stake had never been placed
+        if stake.choice {
+            prediction.yes_pool -= stake.amount;
+        } else {
+            prediction.no_pool -= stake.amount;
+        }
+        storage::set_prediction(&env, &prediction);
+
+        stake.claimed = true;
+        storage::set_stake(&env, prediction_id, &stake);
+
+        pay_out_stake(&env, prediction_id, user, &token, stake.amount);
+
+        Ok(stake.amount)
+    }
+
+    /// Categorical version of `emergency_withdraw`
+    pub fn emergency_withdraw_outcome(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_categorical(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_emergency(&env, &prediction.status)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        let mut stake =
+            storage::get_outcome_stake(&env, prediction_id, &user).ok_or(Error::StakeNotFound)?;
+        if stake.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let pool = prediction.pools.get_unchecked(stake.outcome);
+        prediction.pools.set(stake.outcome, pool - stake.amount);
+        storage::set_categorical(&env, &prediction);
+
+        stake.claimed = true;
+        storage::set_outcome_stake(&env, prediction_id, &stake);
+
+        pay_out_stake(&env, prediction_id, user, &token, stake.amount);
+
+        Ok(stake.amount)
+    }
+
+    /// Scalar version of `emergency_withdraw`
+    pub fn emergency_withdraw_scalar(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_scalar(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_emergency(&env, &prediction.status)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        let mut stake =
+            storage::get_scalar_stake(&env, prediction_id, &user).ok_or(Error::StakeNotFound)?;
+        if stake.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        if stake.long {
+            prediction.long_pool -= stake.amount;
+        } else {
+            prediction.short_pool -= stake.amount;
+        }
+        storage::set_scalar(&env, &prediction);
+
+        stake.claimed = true;
+        storage::set_scalar_stake(&env, prediction_id, &stake);
+
+        pay_out_stake(&env, prediction_id, user, &token, stake.amount);
+
+        Ok(stake.amount)
+    }
+
+    /// LMSR version of `emergency_withdraw`: every share the user holds is
+    /// sold back to the market at its current price
+    pub fn emergency_withdraw_lmsr(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_lmsr(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_emergency(&env, &prediction.status)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        let mut position =
+            storage::get_lmsr_position(&env, prediction_id, &user).ok_or(Error::StakeNotFound)?;
+        if position.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let amount = prediction.sell_back(&mut position);
+        storage::set_lmsr(&env, &prediction);
+
+        position.claimed = true;
+        storage::set_lmsr_position(&env, prediction_id, &position);
+
+        pay_out_stake(&env, prediction_id, user, &token, amount);
+
+        Ok(amount)
+    }
+}
@@ -0,0 +1,166 @@
+//! Per-user position index.
+//!
+//! A user's first stake or trade in a market, including a creator's initial
+//! stake, appends the market to their index, so a wallet can find its markets
+//! without knowing their ids. Top-ups are not indexed again, so a market
+//! appears once. Stakes placed before the index existed are only indexed when their market
+//! is converted by `migrate_prediction`.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env, Vec};
+
+use crate::{
+    is_refundable, storage, Error, PredictionStatus, TimeLockContract, TimeLockContractArgs,
+    TimeLockContractClient, MAX_PAGE_SIZE,
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+#[repr(u32)]
+pub enum MarketKind {
+    Binary = 0,
+    Categorical = 1,
+    Scalar = 2,
+    Lmsr = 3,
+    Cpmm = 4,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Position {
+    pub prediction_id: u64,
+    pub kind: MarketKind,
+    pub side: u32,    // 1 = YES / LONG and 0 = NO / SHORT, or the outcome index
+    pub amount: i128, // For LMSR and CPMM, the shares on `side`, whichever side holds more
+    pub claimed: bool,
+    pub status: PredictionStatus,
+    pub claimable: i128, // What `claim*` or `refund` would pay right now
+}
+
+/// What a position pays right now: its stake back through `refund*` once
+/// the market is cancelled or expired, otherwise its claim, if any.
+fn claimable(
+    status: &PredictionStatus,
+    amount: i128,
+    claimed: bool,
+    payout: Result<i128, Error>,
+) -> i128 {
+    if is_refundable(status) && !claimed {
+        amount
+    } else {
+        payout.unwrap_or(0)
+    }
+}
+
+fn load_position(
+    env: &Env,
+    user: &Address,
+    prediction_id: u64,
+    kind: MarketKind,
+) -> Option<Position> {
+    let position = match kind {
+        MarketKind::Binary => {
+            let prediction = storage::get_prediction(env, prediction_id)?;
+            let stake = storage::get_stake(env, prediction_id, user)?;
+            let payout = prediction.payout_for(&stake);
+            Position {
+                prediction_id,
+                kind,
+                side: stake.choice as u32,
+                amount: stake.amount,
+                claimed: stake.claimed,
+                claimable: claimable(&prediction.status, stake.amount, stake.claimed, payout),
+                status: prediction.status,
+            }
+        }
+        MarketKind::Categorical => {
+            let prediction = storage::get_categorical(env, prediction_id)?;
+            let stake = storage::get_outcome_stake(env, prediction_id, user)?;
+            Position {
+                prediction_id,
+                kind,
+                side: stake.outcome,
+                amount: stake.amount,
+                claimed: stake.claimed,
+                claimable: claimable(
+                    &prediction.status,
+                    stake.amount,
+                    stake.claimed,
+                    prediction.payout_for(&stake),
+                ),
+                status: prediction.status,
+            }
+        }
+        MarketKind::Scalar => {
+            let prediction = storage::get_scalar(env, prediction_id)?;
+            let stake = storage::get_scalar_stake(env, prediction_id, user)?;
+            Position {
+                prediction_id,
+                kind,
+                side: stake.long as u32,
+                amount: stake.amount,
+                claimed: stake.claimed,
+                claimable: claimable(
+                    &prediction.status,
+                    stake.amount,
+                    stake.claimed,
+                    prediction.payout_for(&stake),
+                ),
+                status: prediction.status,
+            }
+        }
+        MarketKind::Lmsr => {
+            let prediction = storage::get_lmsr(env, prediction_id)?;
+            let position = storage::get_lmsr_position(env, prediction_id, user)?;
+            let side = position.yes_shares >= position.no_shares;
+            Position {
+                prediction_id,
+                kind,
+                side: side as u32,
+                amount: position.shares(side),
+                claimed: position.claimed,
+                claimable: prediction.payout_for(&position).unwrap_or(0),
+                status: prediction.status,
+            }
+        }
+        MarketKind::Cpmm => {
+            let prediction = storage::get_cpmm(env, prediction_id)?;
+            let position = storage::get_cpmm_position(env, prediction_id, user)?;
+            let side = position.yes_tokens >= position.no_tokens;
+            Position {
+                prediction_id,
+                kind,
+                side: side as u32,
+                amount: position.tokens(side),
+                claimed: position.claimed,
+                claimable: prediction.payout_for(&position).unwrap_or(0),
+                status: prediction.status,
+            }
+        }
+    };
+    Some(position)
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Get a page of a user's positions, oldest first. `start` is an index
+    /// into the user's positions and `limit` is capped at `MAX_PAGE_SIZE`.
+    pub fn get_user_positions(env: Env, user: Address, start: u32, limit: u32) -> Vec<Position> {
+        let count = storage::get_user_position_count(&env, &user);
+        let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count);
+
+        let mut page = Vec::new(&env);
+        for index in start..end {
+            let position = storage::get_user_position(&env, &user, index)
+                .and_then(|(prediction_id, kind)| load_position(&env, &user, prediction_id, kind));
+            if let Some(position) = position {
+                page.push_back(position);
+            }
+        }
+        page
+    }
+
+    /// Get the number of markets a user has staked in
+    pub fn get_user_position_count(env: Env, user: Address) -> u32 {
+        storage::get_user_position_count(&env, &user)
+    }
+}
@@ -0,0 +1,58 @@
+//! Payout previews for binary predictions.
+//!
+//! Both views go through the same helpers as `stake` and `claim`, so a quote
+//! fails for the same reasons the real call would and a claimable amount is
+//! exactly what `claim` transfers.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env};
+
+use crate::{
+    load_claim, load_stakeable, Error, Settlement, TimeLockContract, TimeLockContractArgs,
+    TimeLockContractClient,
+};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct Claimable {
+    pub amount: i128,
+    pub reason: u32, // Code of the `Error` that `claim` would fail with, or 0
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Projected payout of staking `amount` on `choice` if that side wins,
+    /// given the current pools. Pools can still move before resolution.
+    pub fn quote_stake(
+        env: Env,
+        prediction_id: u64,
+        choice: bool,
+        amount: i128,
+    ) -> Result<i128, Error> {
+        let prediction = load_stakeable(&env, prediction_id, amount)?;
+
+        let (side_pool, other_pool) = if choice {
+            (prediction.yes_pool, prediction.no_pool)
+        } else {
+            (prediction.no_pool, prediction.yes_pool)
+        };
+
+        // With nobody on the other side the prediction resolves as invalid
+        if other_pool == 0 {
+            return Ok(amount);
+        }
+
+        let total_pool = side_pool + other_pool + prediction.forfeited + amount;
+        Ok(Settlement::projected(&env, total_pool, side_pool + amount).payout(amount))
+    }
+
+    /// The amount `claim` would transfer to `user` right now
+    pub fn get_claimable(env: Env, prediction_id: u64, user: Address) -> Claimable {
+        match load_claim(&env, prediction_id, &user) {
+            Ok((_, _, amount)) => Claimable { amount, reason: 0 },
+            Err(error) => Claimable {
+                amount: 0,
+                reason: error as u32,
+            },
+        }
+    }
+}
@@ -0,0 +1,551 @@
+//! Scalar (numeric range) predictions.
+//!
+//! The creator sets a lower and upper bound. Stakers go LONG (the value will
+//! be high) or SHORT (the value will be low). At resolution the resolver reports
+//! the actual value, which is clamped to the bounds, and the prize pool is
+//! split between the two sides linearly: at the lower bound SHORT takes
+//! everything, at the upper bound LONG does, and in between each side gets a
+//! proportional share. Within a side stakers are paid pro-rata. As with
+//! categorical predictions, staking closes and resolution opens at a single
+//! `unlock_time`, with no `lock` step.
+//!
+//! If one side has no stakers there is nobody to pay or nobody to pay from
+//! (e.g. only the creator's initial stake exists), so each side's prize is
+//! its own pool instead and every staker claims back their stake with no fee
+//! taken. The same happens when a resolver resolves the prediction as
+//! invalid. The fee and each side's prize are fixed at resolution.
+//!
+//! Unresolved predictions can be cancelled with `cancel_scalar` on the same
+//! terms as binary ones, or expired with `expire_scalar` once their
+//! resolution deadline passes, after which stakes are returned by
+//! `refund_scalar`.
+
+// `create_scalar` needs both bounds on top of the usual market fields
+#![allow(clippy::too_many_arguments)]
+
+use soroban_sdk::{contractimpl, contracttype, token, Address, Env, String};
+
+use crate::{
+    extended_deadline, has_role, next_prediction_id, paused_total, pay_refund, require_not_paused,
+    require_refundable, require_role, require_unsettled, storage, take_creation_payment,
+    validate_market, Claimed, Error, MarketKind, Operation, PredictionStatus, Role,
+    ScalarCancelled, ScalarCreated, ScalarExpired, ScalarResolved, ScalarResolvedInvalid,
+    ScalarStaked, Settlement, TimeLockContract, TimeLockContractArgs, TimeLockContractClient,
+};
+
+// Keeps `prize_pool * (value - lower_bound)` inside i128 for any realistic pool
+const MAX_RANGE: i128 = 1_000_000_000_000_000_000;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ScalarPrediction {
+    pub id: u64,
+    pub creator: Address,
+    pub question: String,
+    pub token: Address,
+    pub unlock_time: u64,
+    pub resolve_deadline: u64, // Expires if still unresolved by then, see `extended_deadline`
+    pub pause_offset: u64,     // Resolve pause time before creation
+    pub lower_bound: i128,
+    pub upper_bound: i128,
+    pub long_pool: i128,
+    pub short_pool: i128,
+    pub status: PredictionStatus,
+    pub outcome: Option<i128>, // Reported value clamped to the bounds, None if resolved as invalid
+    pub long_prize: i128,
+    pub short_prize: i128,
+    pub settlement: Settlement,
+}
+
+impl ScalarPrediction {
+    /// Split the prize pool between (LONG, SHORT) for the resolved value.
+    fn side_prizes(&self, value: i128, prize_pool: i128) -> (i128, i128) {
+        let long_prize =
+            prize_pool * (value - self.lower_bound) / (self.upper_bound - self.lower_bound);
+        (long_prize, prize_pool - long_prize)
+    }
+
+    /// Settle the prediction so every stake is returned in full, with no fee.
+    fn refund_all(&mut self) {
+        self.status = PredictionStatus::Resolved;
+        self.long_prize = self.long_pool;
+        self.short_prize = self.short_pool;
+        self.settlement = Settlement::refund(self.long_pool + self.short_pool);
+    }
+
+    /// What `claim_scalar` pays out for `stake`, or why it cannot be claimed.
+    pub(crate) fn payout_for(&self, stake: &ScalarStake) -> Result<i128, Error> {
+        match self.status {
+            PredictionStatus::Cancelled => return Err(Error::PredictionCancelled),
+            PredictionStatus::Expired => return Err(Error::ResolutionExpired),
+            PredictionStatus::Resolved => {}
+            _ => return Err(Error::NotResolved),
+        }
+
+        if stake.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let (side_prize, side_pool) = if stake.long {
+            (self.long_prize, self.long_pool)
+        } else {
+            (self.short_prize, self.short_pool)
+        };
+
+        // The value landed at the far end of the range from this side
+        if side_prize == 0 {
+            return Err(Error::LostPrediction);
+        }
+
+        Ok((stake.amount * side_prize) / side_pool)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ScalarStake {
+    pub user: Address,
+    pub long: bool, // true = LONG, false = SHORT
+    pub amount: i128,
+    pub claimed: bool,
+}
+
+/// Load a scalar prediction and check it can be resolved now.
+fn load_resolvable(env: &Env, prediction_id: u64) -> Result<ScalarPrediction, Error> {
+    let prediction = storage::get_scalar(env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+    require_unsettled(&prediction.status)?;
+
+    let current_time = env.ledger().timestamp();
+    if current_time < prediction.unlock_time {
+        return Err(Error::ResolveTooEarly);
+    }
+    if current_time >= extended_deadline(env, prediction.resolve_deadline, prediction.pause_offset)
+    {
+        return Err(Error::ResolutionExpired);
+    }
+
+    Ok(prediction)
+}
+
+/// Pay out a user's claim on a resolved scalar prediction.
+pub(crate) fn settle_scalar_claim(
+    env: &Env,
+    mut prediction: ScalarPrediction,
+    user: &Address,
+) -> Result<i128, Error> {
+    let mut stake =
+        storage::get_scalar_stake(env, prediction.id, user).ok_or(Error::StakeNotFound)?;
+
+    let payout = prediction.payout_for(&stake)?;
+    prediction
+        .settlement
+        .record_claim(env, &prediction.token, stake.amount, payout);
+    storage::set_scalar(env, &prediction);
+
+    stake.claimed = true;
+    storage::set_scalar_stake(env, prediction.id, &stake);
+
+    let token_client = token::Client::new(env, &prediction.token);
+    token_client.transfer(&env.current_contract_address(), user, &payout);
+
+    Claimed {
+        prediction_id: prediction.id,
+        user: user.clone(),
+        stake: stake.amount,
+        payout,
+        platform_fee: prediction.settlement.platform_fee,
+    }
+    .publish(env);
+
+    Ok(payout)
+}
+
+/// Return a user's stake in full from a cancelled or expired scalar
+/// prediction.
+pub(crate) fn refund_scalar_stake(
+    env: &Env,
+    prediction: &ScalarPrediction,
+    user: &Address,
+) -> Result<i128, Error> {
+    let mut stake =
+        storage::get_scalar_stake(env, prediction.id, user).ok_or(Error::StakeNotFound)?;
+    if stake.claimed {
+        return Err(Error::AlreadyClaimed);
+    }
+
+    stake.claimed = true;
+    storage::set_scalar_stake(env, prediction.id, &stake);
+
+    pay_refund(
+        env,
+        prediction.id,
+        user.clone(),
+        &prediction.token,
+        stake.amount,
+    );
+
+    Ok(stake.amount)
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Create a prediction on a numeric value between two bounds. Fails
+    /// with `InvalidBounds` unless `upper_bound` is above `lower_bound` by
+    /// at most 10^18.
+    pub fn create_scalar(
+        env: Env,
+        creator: Address,
+        question: String,
+        lower_bound: i128,
+        upper_bound: i128,
+        unlock_time: u64,
+        initial_long: bool,
+        token: Address,
+    ) -> Result<u64, Error> {
+        creator.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Create)?;
+
+        // Validate inputs
+        let config = storage::get_config(&env);
+        validate_market(&env, &config, &question, unlock_time)?;
+
+        match upper_bound.checked_sub(lower_bound) {
+            Some(range) if range > 0 && range <= MAX_RANGE => {}
+            _ => return Err(Error::InvalidBounds),
+        }
+
+        let count = next_prediction_id(&env);
+        take_creation_payment(&env, &config, &creator, &token);
+
+        let prediction = ScalarPrediction {
+            id: count,
+            creator: creator.clone(),
+            question,
+            token,
+            unlock_time,
+            resolve_deadline: unlock_time + config.resolution_window,
+            pause_offset: paused_total(&env, Operation::Resolve),
+            lower_bound,
+            upper_bound,
+            long_pool: if initial_long { config.min_stake } else { 0 },
+            short_pool: if !initial_long { config.min_stake } else { 0 },
+            status: PredictionStatus::Open,
+            outcome: None,
+            long_prize: 0,
+            short_prize: 0,
+            settlement: Settlement::default(),
+        };
+        storage::set_scalar(&env, &prediction);
+
+        // Store creator's stake
+        let stake = ScalarStake {
+            user: creator,
+            long: initial_long,
+            amount: config.min_stake,
+            claimed: false,
+        };
+        storage::set_scalar_stake(&env, count, &stake);
+        storage::add_user_position(&env, &stake.user, count, MarketKind::Scalar);
+
+        ScalarCreated {
+            prediction_id: count,
+            creator: stake.user,
+            question: prediction.question,
+            token: prediction.token,
+            unlock_time: prediction.unlock_time,
+            resolve_deadline: prediction.resolve_deadline,
+            lower_bound,
+            upper_bound,
+            long: initial_long,
+            amount: config.min_stake,
+            creation_fee: config.creation_fee,
+        }
+        .publish(&env);
+
+        Ok(count)
+    }
+
+    /// Stake LONG or SHORT on a scalar prediction
+    pub fn stake_scalar(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        long: bool,
+        amount: i128,
+        token: Address,
+    ) -> Result<(), Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        // Check if user already staked
+        if storage::get_scalar_stake(&env, prediction_id, &user).is_some() {
+            return Err(Error::AlreadyStaked);
+        }
+
+        // Validate amount
+        if amount < storage::get_config(&env).min_stake {
+            return Err(Error::StakeTooLow);
+        }
+
+        let mut prediction =
+            storage::get_scalar(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        match prediction.status {
+            PredictionStatus::Open => {}
+            _ => return Err(Error::PredictionNotOpen),
+        }
+
+        if env.ledger().timestamp() >= prediction.unlock_time {
+            return Err(Error::PredictionExpired);
+        }
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        // Transfer tokens
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&user, env.current_contract_address(), &amount);
+
+        if long {
+            prediction.long_pool += amount;
+        } else {
+            prediction.short_pool += amount;
+        }
+        storage::set_scalar(&env, &prediction);
+
+        let stake = ScalarStake {
+            user: user.clone(),
+            long,
+            amount,
+            claimed: false,
+        };
+        storage::set_scalar_stake(&env, prediction_id, &stake);
+        storage::add_user_position(&env, &stake.user, prediction_id, MarketKind::Scalar);
+
+        ScalarStaked {
+            prediction_id,
+            user,
+            long,
+            amount,
+            long_pool: prediction.long_pool,
+            short_pool: prediction.short_pool,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Resolve a scalar prediction with the observed value (resolver only).
+    /// Values outside the bounds are clamped.
+    ///
+    /// If either side has no stakers every staker gets their stake back
+    /// instead and no fee is taken.
+    pub fn resolve_scalar(
+        env: Env,
+        resolver: Address,
+        prediction_id: u64,
+        value: i128,
+    ) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
+
+        let mut prediction = load_resolvable(&env, prediction_id)?;
+
+        let outcome = value.clamp(prediction.lower_bound, prediction.upper_bound);
+        prediction.outcome = Some(outcome);
+
+        if prediction.long_pool == 0 || prediction.short_pool == 0 {
+            // Nobody to pay or nobody to pay from: every stake is returned
+            prediction.refund_all();
+        } else {
+            prediction.status = PredictionStatus::Resolved;
+
+            // Only sides with a share of the prize pool are paid
+            let total_pool = prediction.long_pool + prediction.short_pool;
+            let mut eligible_stake = 0;
+            let settlement = Settlement::winners(&env, &prediction.token, total_pool, 0);
+            let (long_prize, short_prize) = prediction.side_prizes(outcome, settlement.prize_pool);
+            if long_prize > 0 {
+                eligible_stake += prediction.long_pool;
+            }
+            if short_prize > 0 {
+                eligible_stake += prediction.short_pool;
+            }
+            prediction.long_prize = long_prize;
+            prediction.short_prize = short_prize;
+            prediction.settlement = Settlement {
+                eligible_stake,
+                ..settlement
+            };
+        }
+        storage::set_scalar(&env, &prediction);
+
+        ScalarResolved {
+            prediction_id,
+            resolver,
+            value,
+            outcome,
+            long_pool: prediction.long_pool,
+            short_pool: prediction.short_pool,
+            platform_fee: prediction.settlement.platform_fee,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Resolve a scalar prediction as invalid when the value cannot be
+    /// determined (resolver only). Every staker can then claim back their
+    /// own stake with `claim_scalar`.
+    pub fn resolve_scalar_invalid(
+        env: Env,
+        resolver: Address,
+        prediction_id: u64,
+    ) -> Result<(), Error> {
+        resolver.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Resolve)?;
+        require_role(&env, Role::Resolver, &resolver)?;
+
+        let mut prediction = load_resolvable(&env, prediction_id)?;
+
+        prediction.refund_all();
+        storage::set_scalar(&env, &prediction);
+
+        ScalarResolvedInvalid {
+            prediction_id,
+            resolver,
+            long_pool: prediction.long_pool,
+            short_pool: prediction.short_pool,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Claim a share of a resolved scalar prediction
+    pub fn claim_scalar(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let prediction =
+            storage::get_scalar(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        settle_scalar_claim(&env, prediction, &user)
+    }
+
+    /// Cancel an unresolved scalar prediction so every staker can take
+    /// their stake back with `refund_scalar`. A resolver can cancel any
+    /// unresolved prediction; the creator can cancel only while nobody else
+    /// has staked. The creation fee is not refunded.
+    pub fn cancel_scalar(env: Env, caller: Address, prediction_id: u64) -> Result<(), Error> {
+        caller.require_auth();
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_scalar(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        if !has_role(&env, Role::Resolver, &caller)? {
+            if caller != prediction.creator {
+                return Err(Error::Unauthorized);
+            }
+            // Only the creator's initial stake may be in the pools
+            let creator_stake = storage::get_scalar_stake(&env, prediction_id, &caller)
+                .map_or(0, |stake| stake.amount);
+            if prediction.long_pool + prediction.short_pool != creator_stake {
+                return Err(Error::CannotCancel);
+            }
+        }
+
+        prediction.status = PredictionStatus::Cancelled;
+        storage::set_scalar(&env, &prediction);
+
+        ScalarCancelled {
+            prediction_id,
+            caller,
+            long_pool: prediction.long_pool,
+            short_pool: prediction.short_pool,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Mark a scalar prediction that was not resolved by its deadline as
+    /// expired so every staker can take their stake back with
+    /// `refund_scalar`. Anyone can call this.
+    pub fn expire_scalar(env: Env, prediction_id: u64) -> Result<(), Error> {
+        storage::extend_instance(&env);
+
+        let mut prediction =
+            storage::get_scalar(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        let deadline =
+            extended_deadline(&env, prediction.resolve_deadline, prediction.pause_offset);
+        if env.ledger().timestamp() < deadline {
+            return Err(Error::DeadlineNotReached);
+        }
+
+        prediction.status = PredictionStatus::Expired;
+        storage::set_scalar(&env, &prediction);
+
+        ScalarExpired {
+            prediction_id,
+            long_pool: prediction.long_pool,
+            short_pool: prediction.short_pool,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Take back the full stake from a cancelled or expired scalar prediction
+    pub fn refund_scalar(
+        env: Env,
+        prediction_id: u64,
+        user: Address,
+        token: Address,
+    ) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Claim)?;
+
+        let prediction =
+            storage::get_scalar(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_refundable(&prediction.status)?;
+
+        if token != prediction.token {
+            return Err(Error::TokenMismatch);
+        }
+
+        refund_scalar_stake(&env, &prediction, &user)
+    }
+
+    /// Get scalar prediction details
+    pub fn get_scalar(env: Env, prediction_id: u64) -> Result<ScalarPrediction, Error> {
+        storage::get_scalar(&env, prediction_id).ok_or(Error::PredictionNotFound)
+    }
+
+    /// Get user stake on a scalar prediction
+    pub fn get_scalar_stake(env: Env, prediction_id: u64, user: Address) -> Option<ScalarStake> {
+        storage::get_scalar_stake(&env, prediction_id, &user)
+    }
+}
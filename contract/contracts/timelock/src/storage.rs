@@ -0,0 +1,345 @@
+//! Storage helpers.
+//!
+//! Contract-wide settings (admin, counters, treasury) live in instance storage.
+//! Predictions and stakes live in persistent storage, one entry each, so an
+//! invocation only loads the markets it touches.
+//!
+//! Contracts deployed before the move kept predictions and stakes in instance
+//! storage. Reads fall back to the old location and every write goes to
+//! persistent storage and drops the instance copy, so old entries migrate the
+//! first time they are modified or passed to `migrate_prediction`. Version 1
+//! predictions use an older layout and are not readable until migrated;
+//! later versions, of every market type alike, are converted as they are
+//! read.
+
+use soroban_sdk::{Address, Env, IntoVal, Map, Symbol, TryFromVal, Val, Vec};
+
+use crate::{
+    decode_config, decode_cpmm, decode_prediction, decode_unlocked_market, CategoricalPrediction,
+    Config, CpmmPosition, CpmmPrediction, DataKey, LmsrPosition, LmsrPrediction, LpPosition,
+    MarketKind, Operation, OutcomeStake, Prediction, Role, ScalarPrediction, ScalarStake, Stake,
+    StakeIncrement,
+};
+
+const DAY_IN_LEDGERS: u32 = 17280; // ~5s per ledger
+
+// Instance storage is extended on every call
+const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+// Predictions and stakes are extended whenever they are read or written
+const PERSISTENT_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+pub fn extend_instance(env: &Env) {
+    env.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+}
+
+/// The protocol parameters, or the defaults for contracts initialized before
+/// they were configurable.
+pub fn get_config(env: &Env) -> Config {
+    let raw: Option<Map<Symbol, Val>> = env.storage().instance().get(&DataKey::Config);
+    raw.and_then(|raw| decode_config(env, raw))
+        .unwrap_or_default()
+}
+
+pub fn set_config(env: &Env, config: &Config) {
+    env.storage().instance().set(&DataKey::Config, config);
+}
+
+/// The schema version of the stored data. Contracts deployed before it was
+/// recorded are version 1.
+pub fn get_schema_version(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::SchemaVersion)
+        .unwrap_or(1)
+}
+
+pub fn set_schema_version(env: &Env, version: u32) {
+    env.storage()
+        .instance()
+        .set(&DataKey::SchemaVersion, &version);
+}
+
+pub fn get_paused_since(env: &Env, operation: Operation) -> Option<u64> {
+    env.storage().instance().get(&DataKey::Paused(operation))
+}
+
+pub fn set_paused_since(env: &Env, operation: Operation, since: Option<u64>) {
+    let key = DataKey::Paused(operation);
+    match since {
+        Some(timestamp) => env.storage().instance().set(&key, &timestamp),
+        None => env.storage().instance().remove(&key),
+    }
+}
+
+/// Total time an operation was paused for, over pauses that have ended.
+pub fn get_paused_total(env: &Env, operation: Operation) -> u64 {
+    env.storage()
+        .instance()
+        .get(&DataKey::PausedTotal(operation))
+        .unwrap_or(0)
+}
+
+pub fn set_paused_total(env: &Env, operation: Operation, total: u64) {
+    env.storage()
+        .instance()
+        .set(&DataKey::PausedTotal(operation), &total);
+}
+
+pub fn get_pending_admin(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&DataKey::PendingAdmin)
+}
+
+pub fn set_pending_admin(env: &Env, new_admin: &Address) {
+    env.storage()
+        .instance()
+        .set(&DataKey::PendingAdmin, new_admin);
+}
+
+pub fn remove_pending_admin(env: &Env) {
+    env.storage().instance().remove(&DataKey::PendingAdmin);
+}
+
+pub fn has_role(env: &Env, role: Role, account: &Address) -> bool {
+    env.storage()
+        .instance()
+        .has(&DataKey::Role(role, account.clone()))
+}
+
+pub fn set_role(env: &Env, role: Role, account: &Address, granted: bool) {
+    let key = DataKey::Role(role, account.clone());
+    if granted {
+        env.storage().instance().set(&key, &true);
+    } else {
+        env.storage().instance().remove(&key);
+    }
+}
+
+fn extend_persistent(env: &Env, key: &DataKey) {
+    env.storage().persistent().extend_ttl(
+        key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+fn get_persistent<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Option<V> {
+    let value = env.storage().persistent().get(key);
+    if value.is_some() {
+        extend_persistent(env, key);
+    }
+    value
+}
+
+fn set_persistent<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    env.storage().persistent().set(key, value);
+    extend_persistent(env, key);
+}
+
+pub fn get_prediction(env: &Env, prediction_id: u64) -> Option<Prediction> {
+    let key = DataKey::Prediction(prediction_id);
+    let stored: Option<Map<Symbol, Val>> = env.storage().persistent().get(&key);
+    if let Some(raw) = stored {
+        extend_persistent(env, &key);
+        return decode_prediction(env, raw);
+    }
+    let legacy: Map<Symbol, Val> = env.storage().instance().get(&key)?;
+    decode_prediction(env, legacy)
+}
+
+pub fn set_prediction(env: &Env, prediction: &Prediction) {
+    let key = DataKey::Prediction(prediction.id);
+    env.storage().persistent().set(&key, prediction);
+    extend_persistent(env, &key);
+    env.storage().instance().remove(&key);
+}
+
+/// Extend the TTL of a prediction, returning false if it is not in persistent storage.
+pub fn extend_prediction(env: &Env, prediction_id: u64) -> bool {
+    let key = DataKey::Prediction(prediction_id);
+    if env.storage().persistent().has(&key) {
+        extend_persistent(env, &key);
+        return true;
+    }
+    false
+}
+
+pub fn get_stake(env: &Env, prediction_id: u64, user: &Address) -> Option<Stake> {
+    let key = DataKey::UserStake(prediction_id, user.clone());
+    if let Some(stake) = env.storage().persistent().get(&key) {
+        extend_persistent(env, &key);
+        return Some(stake);
+    }
+    env.storage().instance().get(&key)
+}
+
+pub fn set_stake(env: &Env, prediction_id: u64, stake: &Stake) {
+    let key = DataKey::UserStake(prediction_id, stake.user.clone());
+    env.storage().persistent().set(&key, stake);
+    extend_persistent(env, &key);
+    env.storage().instance().remove(&key);
+}
+
+pub fn remove_stake(env: &Env, prediction_id: u64, user: &Address) {
+    let key = DataKey::UserStake(prediction_id, user.clone());
+    env.storage().persistent().remove(&key);
+    env.storage().instance().remove(&key);
+}
+
+pub fn get_stake_history(env: &Env, prediction_id: u64, user: &Address) -> Vec<StakeIncrement> {
+    get_persistent(env, &DataKey::StakeHistory(prediction_id, user.clone()))
+        .unwrap_or_else(|| Vec::new(env))
+}
+
+pub fn set_stake_history(
+    env: &Env,
+    prediction_id: u64,
+    user: &Address,
+    history: &Vec<StakeIncrement>,
+) {
+    set_persistent(
+        env,
+        &DataKey::StakeHistory(prediction_id, user.clone()),
+        history,
+    );
+}
+
+pub fn get_categorical(env: &Env, prediction_id: u64) -> Option<CategoricalPrediction> {
+    let raw: Map<Symbol, Val> = get_persistent(env, &DataKey::Categorical(prediction_id))?;
+    decode_unlocked_market(env, raw)
+}
+
+pub fn set_categorical(env: &Env, prediction: &CategoricalPrediction) {
+    set_persistent(env, &DataKey::Categorical(prediction.id), prediction);
+}
+
+pub fn get_outcome_stake(env: &Env, prediction_id: u64, user: &Address) -> Option<OutcomeStake> {
+    get_persistent(env, &DataKey::OutcomeStake(prediction_id, user.clone()))
+}
+
+pub fn set_outcome_stake(env: &Env, prediction_id: u64, stake: &OutcomeStake) {
+    set_persistent(
+        env,
+        &DataKey::OutcomeStake(prediction_id, stake.user.clone()),
+        stake,
+    );
+}
+
+pub fn get_scalar(env: &Env, prediction_id: u64) -> Option<ScalarPrediction> {
+    let raw: Map<Symbol, Val> = get_persistent(env, &DataKey::Scalar(prediction_id))?;
+    decode_unlocked_market(env, raw)
+}
+
+pub fn set_scalar(env: &Env, prediction: &ScalarPrediction) {
+    set_persistent(env, &DataKey::Scalar(prediction.id), prediction);
+}
+
+pub fn get_scalar_stake(env: &Env, prediction_id: u64, user: &Address) -> Option<ScalarStake> {
+    get_persistent(env, &DataKey::ScalarStake(prediction_id, user.clone()))
+}
+
+pub fn set_scalar_stake(env: &Env, prediction_id: u64, stake: &ScalarStake) {
+    set_persistent(
+        env,
+        &DataKey::ScalarStake(prediction_id, stake.user.clone()),
+        stake,
+    );
+}
+
+pub fn get_lmsr(env: &Env, prediction_id: u64) -> Option<LmsrPrediction> {
+    let raw: Map<Symbol, Val> = get_persistent(env, &DataKey::Lmsr(prediction_id))?;
+    decode_unlocked_market(env, raw)
+}
+
+pub fn set_lmsr(env: &Env, prediction: &LmsrPrediction) {
+    set_persistent(env, &DataKey::Lmsr(prediction.id), prediction);
+}
+
+pub fn get_lmsr_position(env: &Env, prediction_id: u64, user: &Address) -> Option<LmsrPosition> {
+    get_persistent(env, &DataKey::LmsrPosition(prediction_id, user.clone()))
+}
+
+pub fn set_lmsr_position(env: &Env, prediction_id: u64, position: &LmsrPosition) {
+    set_persistent(
+        env,
+        &DataKey::LmsrPosition(prediction_id, position.user.clone()),
+        position,
+    );
+}
+
+pub fn get_cpmm(env: &Env, prediction_id: u64) -> Option<CpmmPrediction> {
+    let raw: Map<Symbol, Val> = get_persistent(env, &DataKey::Cpmm(prediction_id))?;
+    decode_cpmm(env, raw)
+}
+
+pub fn set_cpmm(env: &Env, prediction: &CpmmPrediction) {
+    set_persistent(env, &DataKey::Cpmm(prediction.id), prediction);
+}
+
+pub fn get_cpmm_position(env: &Env, prediction_id: u64, user: &Address) -> Option<CpmmPosition> {
+    get_persistent(env, &DataKey::CpmmPosition(prediction_id, user.clone()))
+}
+
+pub fn set_cpmm_position(env: &Env, prediction_id: u64, position: &CpmmPosition) {
+    set_persistent(
+        env,
+        &DataKey::CpmmPosition(prediction_id, position.user.clone()),
+        position,
+    );
+}
+
+pub fn get_lp_position(env: &Env, prediction_id: u64, provider: &Address) -> Option<LpPosition> {
+    get_persistent(env, &DataKey::LpPosition(prediction_id, provider.clone()))
+}
+
+pub fn set_lp_position(env: &Env, prediction_id: u64, position: &LpPosition) {
+    set_persistent(
+        env,
+        &DataKey::LpPosition(prediction_id, position.provider.clone()),
+        position,
+    );
+}
+
+/// The type of the market with this id. Ids are shared by every market type.
+pub fn get_market_kind(env: &Env, prediction_id: u64) -> Option<MarketKind> {
+    let persistent = env.storage().persistent();
+    let binary = DataKey::Prediction(prediction_id);
+    if persistent.has(&binary) || env.storage().instance().has(&binary) {
+        Some(MarketKind::Binary)
+    } else if persistent.has(&DataKey::Categorical(prediction_id)) {
+        Some(MarketKind::Categorical)
+    } else if persistent.has(&DataKey::Scalar(prediction_id)) {
+        Some(MarketKind::Scalar)
+    } else if persistent.has(&DataKey::Lmsr(prediction_id)) {
+        Some(MarketKind::Lmsr)
+    } else if persistent.has(&DataKey::Cpmm(prediction_id)) {
+        Some(MarketKind::Cpmm)
+    } else {
+        None
+    }
+}
+
+pub fn get_user_position_count(env: &Env, user: &Address) -> u32 {
+    get_persistent(env, &DataKey::UserPositionCount(user.clone())).unwrap_or(0)
+}
+
+/// The market at `index` in the user's position index, or None if the entry
+/// is missing, e.g. because it was archived.
+pub fn get_user_position(env: &Env, user: &Address, index: u32) -> Option<(u64, MarketKind)> {
+    get_persistent(env, &DataKey::UserPosition(user.clone(), index))
+}
+
+/// Append a market to the user's position index.
+pub fn add_user_position(env: &Env, user: &Address, prediction_id: u64, kind: MarketKind) {
+    let count = get_user_position_count(env, user);
+    set_persistent(
+        env,
+        &DataKey::UserPosition(user.clone(), count),
+        &(prediction_id, kind),
+    );
+    set_persistent(env, &DataKey::UserPositionCount(user.clone()), &(count + 1));
+}
@@ -3,11 +3,17 @@ extern crate std;
 
 use super::*;
 use soroban_sdk::{
-    testutils::{Address as _, Ledger, LedgerInfo},
-    token, Address, Env, String,
+    testutils::{
+        storage::Persistent as _, Address as _, Events, Ledger, LedgerInfo, MockAuth,
+        MockAuthInvoke,
+    },
+    token, vec, Address, BytesN, Env, Event, IntoVal, Map, String, Symbol, TryFromVal, Val, Vec,
 };
 
-fn create_token_contract<'a>(env: &Env, admin: &Address) -> (token::Client<'a>, token::StellarAssetClient<'a>) {
+fn create_token_contract<'a>(
+    env: &Env,
+    admin: &Address,
+) -> (token::Client<'a>, token::StellarAssetClient<'a>) {
     let contract_address = env.register_stellar_asset_contract_v2(admin.clone());
     (
         token::Client::new(env, &contract_address.address()),
@@ -15,23 +21,35 @@ fn create_token_contract<'a>(env: &Env, admin: &Address) -> (token::Client<'a>,
     )
 }
 
+fn assert_last_event(env: &Env, contract_id: &Address, event: &impl Event) {
+    let events = env.events().all();
+    let last = events.slice(events.len() - 1..);
+    assert_eq!(
+        last,
+        vec![
+            env,
+            (contract_id.clone(), event.topics(env), event.data(env))
+        ]
+    );
+}
+
 #[test]
 fn test_initialize() {
     let env = Env::default();
     env.mock_all_auths();
 
     let admin = Address::generate(&env);
+    let (token, _) = create_token_contract(&env, &admin);
     let contract_id = env.register(TimeLockContract, ());
     let client = TimeLockContractClient::new(&env, &contract_id);
 
     client.initialize(&admin);
 
     assert_eq!(client.get_prediction_count(), 0);
-    assert_eq!(client.get_treasury(), 0);
+    assert_eq!(client.get_treasury(&token.address), 0);
 }
 
 #[test]
-#[should_panic(expected = "Already initialized")]
 fn test_double_initialize() {
     let env = Env::default();
     env.mock_all_auths();
@@ -41,7 +59,10 @@ fn test_double_initialize() {
     let client = TimeLockContractClient::new(&env, &contract_id);
 
     client.initialize(&admin);
-    client.initialize(&admin); // Should panic
+    assert_eq!(
+        client.try_initialize(&admin),
+        Err(Ok(Error::AlreadyInitialized))
+    );
 }
 
 #[test]
@@ -81,6 +102,7 @@ fn test_create_prediction() {
         &creator,
         &question,
         &unlock_time,
+        &unlock_time,
         &initial_choice,
         &token.address,
     );
@@ -91,12 +113,11 @@ fn test_create_prediction() {
     let prediction = client.get_prediction(&prediction_id);
     assert_eq!(prediction.id, 1);
     assert_eq!(prediction.question, question);
-    assert_eq!(prediction.yes_pool, MIN_STAKE);
+    assert_eq!(prediction.yes_pool, DEFAULT_MIN_STAKE);
     assert_eq!(prediction.no_pool, 0);
 }
 
 #[test]
-#[should_panic(expected = "Unlock time must be at least 1 hour in the future")]
 fn test_create_prediction_invalid_time() {
     let env = Env::default();
     env.mock_all_auths();
@@ -124,11 +145,18 @@ fn test_create_prediction_invalid_time() {
     let question = String::from_str(&env, "Will Bitcoin hit $100k?");
     let unlock_time = 1000000 + 1800; // Only 30 minutes - should fail
 
-    client.create_prediction(&creator, &question, &unlock_time, &true, &token.address);
+    let result = client.try_create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidUnlockTime)));
 }
 
 #[test]
-#[should_panic(expected = "Question must be 10-200 characters")]
 fn test_create_prediction_short_question() {
     let env = Env::default();
     env.mock_all_auths();
@@ -156,7 +184,15 @@ fn test_create_prediction_short_question() {
     let question = String::from_str(&env, "Too short"); // Only 9 chars
     let unlock_time = 1000000 + 7200;
 
-    client.create_prediction(&creator, &question, &unlock_time, &true, &token.address);
+    let result = client.try_create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidQuestion)));
 }
 
 #[test]
@@ -194,6 +230,7 @@ fn test_stake() {
         &creator,
         &question,
         &unlock_time,
+        &unlock_time,
         &true,
         &token.address,
     );
@@ -202,16 +239,15 @@ fn test_stake() {
     client.stake(&prediction_id, &user, &false, &200_0000000, &token.address);
 
     let prediction = client.get_prediction(&prediction_id);
-    assert_eq!(prediction.yes_pool, MIN_STAKE);
+    assert_eq!(prediction.yes_pool, DEFAULT_MIN_STAKE);
     assert_eq!(prediction.no_pool, 200_0000000);
 
     let user_stake = client.get_stake(&prediction_id, &user).unwrap();
-    assert_eq!(user_stake.choice, false);
+    assert!(!user_stake.choice);
     assert_eq!(user_stake.amount, 200_0000000);
 }
 
 #[test]
-#[should_panic(expected = "Already staked on this prediction")]
 fn test_double_stake() {
     let env = Env::default();
     env.mock_all_auths();
@@ -246,12 +282,14 @@ fn test_double_stake() {
         &creator,
         &question,
         &unlock_time,
+        &unlock_time,
         &true,
         &token.address,
     );
 
     client.stake(&prediction_id, &user, &false, &200_0000000, &token.address);
-    client.stake(&prediction_id, &user, &true, &100_0000000, &token.address); // Should panic
+    let result = client.try_stake(&prediction_id, &user, &true, &100_0000000, &token.address);
+    assert_eq!(result, Err(Ok(Error::CannotSwitchSides)));
 }
 
 #[test]
@@ -291,6 +329,7 @@ fn test_resolve_and_claim() {
         &creator,
         &question,
         &unlock_time,
+        &unlock_time,
         &true,
         &token.address,
     );
@@ -312,10 +351,11 @@ fn test_resolve_and_claim() {
     });
 
     // Admin resolves: YES wins
+    client.lock(&prediction_id);
     client.resolve(&admin, &prediction_id, &true);
 
     let prediction = client.get_prediction(&prediction_id);
-    assert_eq!(prediction.winner, Some(true));
+    assert_eq!(prediction.winner, Resolution::Yes);
 
     // Creator and user1 can claim (both voted YES)
     let creator_payout = client.claim(&prediction_id, &creator, &token.address);
@@ -332,7 +372,6 @@ fn test_resolve_and_claim() {
 }
 
 #[test]
-#[should_panic(expected = "Cannot resolve before unlock time")]
 fn test_resolve_too_early() {
     let env = Env::default();
     env.mock_all_auths();
@@ -365,16 +404,135 @@ fn test_resolve_too_early() {
         &creator,
         &question,
         &unlock_time,
+        &unlock_time,
         &true,
         &token.address,
     );
 
     // Try to resolve immediately - should fail
-    client.resolve(&admin, &prediction_id, &true);
+    let result = client.try_resolve(&admin, &prediction_id, &true);
+    assert_eq!(result, Err(Ok(Error::PredictionNotLocked)));
+    let result = client.try_lock(&prediction_id);
+    assert_eq!(result, Err(Ok(Error::LockTooEarly)));
+}
+
+#[test]
+fn test_close_before_resolve() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // "Will X happen by Friday": bets close two days before the answer is known
+    let question = String::from_str(&env, "Will X happen by Friday?");
+    let close_time = 1000000 + 7200;
+    let resolve_after = close_time + 2 * 24 * 3600;
+
+    let result = client.try_create_prediction(
+        &creator,
+        &question,
+        &close_time,
+        &(close_time - 1),
+        &true,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidResolveTime)));
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &close_time,
+        &resolve_after,
+        &true,
+        &token.address,
+    );
+    let prediction = client.get_prediction(&prediction_id);
+    assert_eq!(prediction.close_time, close_time);
+    assert_eq!(prediction.resolve_after, resolve_after);
+    client.stake(&prediction_id, &user, &false, &200_0000000, &token.address);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: close_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Staking has closed, but the market is still Open until someone locks it
+    let result = client.try_stake(&prediction_id, &admin, &false, &200_0000000, &token.address);
+    assert_eq!(result, Err(Ok(Error::PredictionExpired)));
+    assert_eq!(
+        client.try_resolve(&admin, &prediction_id, &true),
+        Err(Ok(Error::PredictionNotLocked))
+    );
+
+    client.lock(&prediction_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &PredictionLocked {
+            prediction_id,
+            yes_pool: DEFAULT_MIN_STAKE,
+            no_pool: 200_0000000,
+        },
+    );
+    assert_eq!(
+        client.get_prediction(&prediction_id).status,
+        PredictionStatus::Locked
+    );
+    assert_eq!(
+        client.try_lock(&prediction_id),
+        Err(Ok(Error::PredictionNotOpen))
+    );
+    assert_eq!(
+        client.try_resolve(&admin, &prediction_id, &true),
+        Err(Ok(Error::ResolveTooEarly))
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: resolve_after,
+        protocol_version: 23,
+        sequence_number: 30,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.resolve(&admin, &prediction_id, &false);
+    assert_eq!(
+        client.get_prediction(&prediction_id).status,
+        PredictionStatus::Resolved
+    );
 }
 
 #[test]
-#[should_panic(expected = "You lost this prediction")]
 fn test_claim_loser() {
     let env = Env::default();
     env.mock_all_auths();
@@ -409,6 +567,7 @@ fn test_claim_loser() {
         &creator,
         &question,
         &unlock_time,
+        &unlock_time,
         &true,
         &token.address,
     );
@@ -426,8 +585,5459 @@ fn test_claim_loser() {
         max_entry_ttl: 3110400,
     });
 
+    client.lock(&prediction_id);
     client.resolve(&admin, &prediction_id, &true); // YES wins
 
-    // Loser voted NO, should panic
-    client.claim(&prediction_id, &loser, &token.address);
+    // Loser voted NO, should fail
+    let result = client.try_claim(&prediction_id, &loser, &token.address);
+    assert_eq!(result, Err(Ok(Error::LostPrediction)));
+}
+
+#[test]
+fn test_resolve_not_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let stranger = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_resolve(&stranger, &prediction_id, &true);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+
+    let result = client.try_withdraw_treasury(&stranger, &1, &token.address);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+}
+
+#[test]
+fn test_error_paths() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    assert_eq!(
+        client.try_get_prediction(&1),
+        Err(Ok(Error::PredictionNotFound))
+    );
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    let result = client.try_stake(
+        &prediction_id,
+        &user,
+        &false,
+        &(DEFAULT_MIN_STAKE - 1),
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::StakeTooLow)));
+
+    let result = client.try_claim(&prediction_id, &creator, &token.address);
+    assert_eq!(result, Err(Ok(Error::NotResolved)));
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_stake(
+        &prediction_id,
+        &user,
+        &false,
+        &DEFAULT_MIN_STAKE,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::PredictionExpired)));
+
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &true);
+    let result = client.try_resolve(&admin, &prediction_id, &true);
+    assert_eq!(result, Err(Ok(Error::AlreadyResolved)));
+
+    let result = client.try_claim(&prediction_id, &user, &token.address);
+    assert_eq!(result, Err(Ok(Error::StakeNotFound)));
+
+    client.claim(&prediction_id, &creator, &token.address);
+    let result = client.try_claim(&prediction_id, &creator, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+
+    let treasury = client.get_treasury(&token.address);
+    let result = client.try_withdraw_treasury(&admin, &(treasury + 1), &token.address);
+    assert_eq!(result, Err(Ok(Error::InsufficientTreasury)));
+    let result = client.try_withdraw_treasury(&admin, &0, &token.address);
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+    let result = client.try_withdraw_treasury(&admin, &-1, &token.address);
+    assert_eq!(result, Err(Ok(Error::InvalidAmount)));
+    assert_eq!(client.get_treasury(&token.address), treasury);
+}
+
+#[test]
+fn test_token_bound_to_prediction() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    let (other_token, other_token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+    other_token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_eq!(client.get_prediction(&prediction_id).token, token.address);
+
+    // Staking with a different asset is rejected
+    let result = client.try_stake(
+        &prediction_id,
+        &user,
+        &false,
+        &DEFAULT_MIN_STAKE,
+        &other_token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::TokenMismatch)));
+    client.stake(
+        &prediction_id,
+        &user,
+        &false,
+        &DEFAULT_MIN_STAKE,
+        &token.address,
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &true);
+
+    // Claiming in a different asset is rejected
+    let result = client.try_claim(&prediction_id, &creator, &other_token.address);
+    assert_eq!(result, Err(Ok(Error::TokenMismatch)));
+    client.claim(&prediction_id, &creator, &token.address);
+
+    // Treasury is credited only in the prediction's token
+    assert!(client.get_treasury(&token.address) > 0);
+    assert_eq!(client.get_treasury(&other_token.address), 0);
+    let result = client.try_withdraw_treasury(&admin, &1, &other_token.address);
+    assert_eq!(result, Err(Ok(Error::InsufficientTreasury)));
+}
+
+#[test]
+fn test_events() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_last_event(
+        &env,
+        &contract_id,
+        &PredictionCreated {
+            prediction_id,
+            creator: creator.clone(),
+            question,
+            token: token.address.clone(),
+            close_time: unlock_time,
+            resolve_after: unlock_time,
+            resolve_deadline: unlock_time + DEFAULT_RESOLUTION_WINDOW,
+            choice: true,
+            amount: DEFAULT_MIN_STAKE,
+            creation_fee: DEFAULT_CREATION_FEE,
+            yes_pool: DEFAULT_MIN_STAKE,
+            no_pool: 0,
+        },
+    );
+
+    client.stake(&prediction_id, &user, &false, &300_0000000, &token.address);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Staked {
+            prediction_id,
+            user: user.clone(),
+            choice: false,
+            amount: 300_0000000,
+            total: 300_0000000,
+            yes_pool: DEFAULT_MIN_STAKE,
+            no_pool: 300_0000000,
+        },
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &true);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Resolved {
+            prediction_id,
+            resolver: admin.clone(),
+            winner: true,
+            yes_pool: DEFAULT_MIN_STAKE,
+            no_pool: 300_0000000,
+            platform_fee: 20_0000000,
+        },
+    );
+
+    // Whole pool of 400 minus the 5% fee goes to the only winner
+    let payout = client.claim(&prediction_id, &creator, &token.address);
+    assert_eq!(payout, 380_0000000);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Claimed {
+            prediction_id,
+            user: creator.clone(),
+            stake: DEFAULT_MIN_STAKE,
+            payout,
+            platform_fee: 20_0000000,
+        },
+    );
+
+    let treasury = client.get_treasury(&token.address);
+    client.withdraw_treasury(&admin, &10_0000000, &token.address);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &TreasuryWithdrawn {
+            token: token.address.clone(),
+            treasurer: admin.clone(),
+            amount: 10_0000000,
+            remaining: treasury - 10_0000000,
+        },
+    );
+}
+
+#[test]
+fn test_persistent_storage_and_bump() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    let prediction_key = DataKey::Prediction(prediction_id);
+    let stake_key = DataKey::UserStake(prediction_id, creator.clone());
+    let initial_ttl = env.as_contract(&contract_id, || {
+        assert!(!env.storage().instance().has(&prediction_key));
+        assert!(!env.storage().instance().has(&stake_key));
+        assert!(env.storage().persistent().has(&stake_key));
+        env.storage().persistent().get_ttl(&prediction_key)
+    });
+
+    // Two days later the entry is below the extension threshold
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000 + 2 * 86400,
+        protocol_version: 23,
+        sequence_number: 10 + 2 * 17280,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.bump_prediction(&prediction_id);
+    let bumped_ttl = env.as_contract(&contract_id, || {
+        env.storage().persistent().get_ttl(&prediction_key)
+    });
+    assert_eq!(bumped_ttl, initial_ttl);
+
+    assert_eq!(
+        client.try_bump_prediction(&99),
+        Err(Ok(Error::PredictionNotFound))
+    );
+}
+
+#[test]
+fn test_migrate_instance_storage() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, _) = create_token_contract(&env, &admin);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    // Simulate a market written by an earlier version into instance storage
+    let prediction = Prediction {
+        id: 1,
+        creator: creator.clone(),
+        question: String::from_str(&env, "Will Bitcoin hit $100k?"),
+        token: token.address.clone(),
+        close_time: 1000000 + 7200,
+        resolve_after: 1000000 + 7200,
+        resolve_deadline: 1000000 + 7200 + DEFAULT_RESOLUTION_WINDOW,
+        pause_offset: 0,
+        yes_pool: DEFAULT_MIN_STAKE,
+        no_pool: DEFAULT_MIN_STAKE,
+        forfeited: 0,
+        status: PredictionStatus::Open,
+        winner: Resolution::Pending,
+        settlement: Settlement::default(),
+    };
+    let creator_stake = Stake {
+        user: creator.clone(),
+        choice: true,
+        amount: DEFAULT_MIN_STAKE,
+        claimed: false,
+    };
+    let user_stake = Stake {
+        user: user.clone(),
+        choice: false,
+        amount: DEFAULT_MIN_STAKE,
+        claimed: false,
+    };
+    env.as_contract(&contract_id, || {
+        let storage = env.storage().instance();
+        storage.set(&DataKey::PredictionCount, &1u64);
+        storage.set(&DataKey::Prediction(1), &prediction);
+        storage.set(&DataKey::UserStake(1, creator.clone()), &creator_stake);
+        storage.set(&DataKey::UserStake(1, user.clone()), &user_stake);
+    });
+
+    // Legacy entries are still readable
+    assert_eq!(client.get_prediction(&1), prediction);
+    assert_eq!(client.get_stake(&1, &user), Some(user_stake.clone()));
+
+    let stranger = Address::generate(&env);
+    let result = client.try_migrate_prediction(&admin, &1, &vec![&env, stranger.clone()]);
+    assert_eq!(result, Ok(Ok(0)));
+    let result = client.try_migrate_prediction(&stranger, &1, &vec![&env]);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+
+    let moved = client.migrate_prediction(&admin, &1, &vec![&env, creator.clone(), user.clone()]);
+    assert_eq!(moved, 2);
+
+    env.as_contract(&contract_id, || {
+        assert!(!env.storage().instance().has(&DataKey::Prediction(1)));
+        assert!(!env
+            .storage()
+            .instance()
+            .has(&DataKey::UserStake(1, user.clone())));
+        assert!(env.storage().persistent().has(&DataKey::Prediction(1)));
+        assert!(env
+            .storage()
+            .persistent()
+            .has(&DataKey::UserStake(1, user.clone())));
+    });
+    assert_eq!(client.get_prediction(&1), prediction);
+    assert_eq!(client.get_stake(&1, &user), Some(user_stake));
+}
+
+#[test]
+fn test_categorical_prediction() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Which team wins the league?");
+    let unlock_time = 1000000 + 7200;
+
+    // At least two outcomes are required
+    let result = client.try_create_categorical(
+        &creator,
+        &question,
+        &vec![&env, String::from_str(&env, "Reds")],
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidOutcomes)));
+
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+        String::from_str(&env, "Greens"),
+    ];
+    let prediction_id = client.create_categorical(
+        &creator,
+        &question,
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    assert_eq!(client.get_prediction_count(), 1);
+    assert_eq!(
+        client.try_get_prediction(&prediction_id),
+        Err(Ok(Error::PredictionNotFound))
+    );
+
+    let result = client.try_stake_outcome(
+        &prediction_id,
+        &user1,
+        &3,
+        &DEFAULT_MIN_STAKE,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidOutcome)));
+
+    client.stake_outcome(&prediction_id, &user1, &1, &200_0000000, &token.address);
+    client.stake_outcome(&prediction_id, &user2, &2, &300_0000000, &token.address);
+
+    let prediction = client.get_categorical(&prediction_id);
+    assert_eq!(prediction.outcomes, outcomes);
+    assert_eq!(
+        prediction.pools,
+        vec![&env, DEFAULT_MIN_STAKE, 200_0000000, 300_0000000]
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_resolve_outcome(&admin, &prediction_id, &3);
+    assert_eq!(result, Err(Ok(Error::InvalidOutcome)));
+    client.resolve_outcome(&admin, &prediction_id, &1);
+    assert_eq!(client.get_categorical(&prediction_id).winner, Some(1));
+
+    // Total pool = 100 + 200 + 300 = 600, after 5% fee = 570, all to user1
+    let payout = client.claim_outcome(&prediction_id, &user1, &token.address);
+    assert_eq!(payout, 570_0000000);
+    assert_eq!(token.balance(&user1), 800_0000000 + payout);
+
+    let result = client.try_claim_outcome(&prediction_id, &creator, &token.address);
+    assert_eq!(result, Err(Ok(Error::LostPrediction)));
+    let result = client.try_claim_outcome(&prediction_id, &user1, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+}
+
+#[test]
+fn test_scalar_prediction() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "BTC price at end of year?");
+    let unlock_time = 1000000 + 7200;
+
+    let result = client.try_create_scalar(
+        &creator,
+        &question,
+        &150_000,
+        &50_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidBounds)));
+
+    let prediction_id = client.create_scalar(
+        &creator,
+        &question,
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    client.stake_scalar(&prediction_id, &user1, &true, &300_0000000, &token.address);
+    client.stake_scalar(&prediction_id, &user2, &false, &400_0000000, &token.address);
+
+    let prediction = client.get_scalar(&prediction_id);
+    assert_eq!(prediction.long_pool, 400_0000000);
+    assert_eq!(prediction.short_pool, 400_0000000);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.resolve_scalar(&admin, &prediction_id, &125_000);
+    assert_eq!(client.get_scalar(&prediction_id).outcome, Some(125_000));
+
+    // Total pool = 800, after 5% fee = 760
+    // Value is 75% of the way up the range: LONG gets 570, SHORT gets 190
+    // Creator gets (100/400) * 570, user1 gets (300/400) * 570
+    assert_eq!(
+        client.claim_scalar(&prediction_id, &creator, &token.address),
+        142_5000000
+    );
+    assert_eq!(
+        client.claim_scalar(&prediction_id, &user1, &token.address),
+        427_5000000
+    );
+    assert_eq!(
+        client.claim_scalar(&prediction_id, &user2, &token.address),
+        190_0000000
+    );
+}
+
+#[test]
+fn test_scalar_clamps_outcome() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "BTC price at end of year?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_scalar(
+        &creator,
+        &question,
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake_scalar(
+        &prediction_id,
+        &user,
+        &false,
+        &DEFAULT_MIN_STAKE,
+        &token.address,
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Above the upper bound: LONG takes the whole prize pool
+    client.resolve_scalar(&admin, &prediction_id, &200_000);
+    assert_eq!(client.get_scalar(&prediction_id).outcome, Some(150_000));
+
+    assert_eq!(
+        client.claim_scalar(&prediction_id, &creator, &token.address),
+        190_0000000
+    );
+    let result = client.try_claim_scalar(&prediction_id, &user, &token.address);
+    assert_eq!(result, Err(Ok(Error::LostPrediction)));
+}
+
+#[test]
+fn test_scalar_range_limit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Total value locked at end of year?");
+    let unlock_time = 1000000 + 7200;
+
+    // Ranges too wide to split the prize pool over are rejected up front
+    let result = client.try_create_scalar(
+        &creator,
+        &question,
+        &i128::MIN,
+        &i128::MAX,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidBounds)));
+    let result = client.try_create_scalar(
+        &creator,
+        &question,
+        &-1,
+        &1_000_000_000_000_000_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidBounds)));
+
+    // The widest allowed range still resolves anywhere within it
+    let prediction_id = client.create_scalar(
+        &creator,
+        &question,
+        &0,
+        &1_000_000_000_000_000_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake_scalar(
+        &prediction_id,
+        &user,
+        &false,
+        &DEFAULT_MIN_STAKE,
+        &token.address,
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Halfway up the range each side gets half of the 190 prize pool
+    client.resolve_scalar(&admin, &prediction_id, &500_000_000_000_000_000);
+    assert_eq!(
+        client.claim_scalar(&prediction_id, &creator, &token.address),
+        95_0000000
+    );
+    assert_eq!(
+        client.claim_scalar(&prediction_id, &user, &token.address),
+        95_0000000
+    );
+}
+
+#[test]
+fn test_admin_cancel_and_refund() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user, &false, &250_0000000, &token.address);
+
+    let result = client.try_refund(&prediction_id, &user, &token.address);
+    assert_eq!(result, Err(Ok(Error::NotCancelled)));
+
+    client.cancel_prediction(&admin, &prediction_id);
+    assert_eq!(
+        client.get_prediction(&prediction_id).status,
+        PredictionStatus::Cancelled
+    );
+
+    // Every staker gets back exactly what they put in
+    assert_eq!(
+        client.refund(&prediction_id, &user, &token.address),
+        250_0000000
+    );
+    assert_eq!(
+        client.refund(&prediction_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(token.balance(&user), 1000_0000000);
+    assert_eq!(token.balance(&creator), 1000_0000000 - DEFAULT_CREATION_FEE);
+
+    let result = client.try_refund(&prediction_id, &user, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+
+    // The creation fee stays in the treasury
+    assert_eq!(
+        client.get_treasury(&token.address),
+        (DEFAULT_CREATION_FEE * 7) / 10
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_resolve(&admin, &prediction_id, &true);
+    assert_eq!(result, Err(Ok(Error::PredictionCancelled)));
+    let result = client.try_claim(&prediction_id, &creator, &token.address);
+    assert_eq!(result, Err(Ok(Error::PredictionCancelled)));
+    let result = client.try_cancel_prediction(&admin, &prediction_id);
+    assert_eq!(result, Err(Ok(Error::PredictionCancelled)));
+}
+
+#[test]
+fn test_creator_cancel() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let first_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    let second_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    // Nobody else has staked: the creator may back out
+    let result = client.try_cancel_prediction(&user, &first_id);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+    client.cancel_prediction(&creator, &first_id);
+    assert_eq!(
+        client.refund(&first_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+
+    // Once someone else has staked only the admin can cancel
+    client.stake(
+        &second_id,
+        &user,
+        &false,
+        &DEFAULT_MIN_STAKE,
+        &token.address,
+    );
+    let result = client.try_cancel_prediction(&creator, &second_id);
+    assert_eq!(result, Err(Ok(Error::CannotCancel)));
+}
+
+#[test]
+fn test_cancel_categorical_and_scalar() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let unlock_time = 1000000 + 7200;
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    let categorical_id = client.create_categorical(
+        &creator,
+        &String::from_str(&env, "Which team wins the league?"),
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    let scalar_id = client.create_scalar(
+        &creator,
+        &String::from_str(&env, "Where will BTC close the year?"),
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    // Nobody else has staked on the scalar market: the creator may back out
+    let result = client.try_cancel_scalar(&user, &scalar_id);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+    client.cancel_scalar(&creator, &scalar_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &ScalarCancelled {
+            prediction_id: scalar_id,
+            caller: creator.clone(),
+            long_pool: DEFAULT_MIN_STAKE,
+            short_pool: 0,
+        },
+    );
+    assert_eq!(
+        client.refund_scalar(&scalar_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    let result = client.try_refund_scalar(&scalar_id, &creator, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+
+    // Once someone else has staked only a resolver can cancel
+    client.stake_outcome(&categorical_id, &user, &1, &250_0000000, &token.address);
+    let result = client.try_refund_outcome(&categorical_id, &user, &token.address);
+    assert_eq!(result, Err(Ok(Error::NotCancelled)));
+    let result = client.try_cancel_outcome(&creator, &categorical_id);
+    assert_eq!(result, Err(Ok(Error::CannotCancel)));
+    client.cancel_outcome(&admin, &categorical_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &OutcomeCancelled {
+            prediction_id: categorical_id,
+            caller: admin.clone(),
+            pools: vec![&env, DEFAULT_MIN_STAKE, 250_0000000],
+        },
+    );
+
+    let positions = client.get_user_positions(&user, &0, &10);
+    assert_eq!(positions.get_unchecked(0).claimable, 250_0000000);
+
+    assert_eq!(
+        client.refund_outcome(&categorical_id, &user, &token.address),
+        250_0000000
+    );
+    assert_eq!(
+        client.refund_outcome(&categorical_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(token.balance(&user), 1000_0000000);
+    assert_eq!(
+        token.balance(&creator),
+        1000_0000000 - 2 * DEFAULT_CREATION_FEE
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_resolve_outcome(&admin, &categorical_id, &1);
+    assert_eq!(result, Err(Ok(Error::PredictionCancelled)));
+    let result = client.try_claim_outcome(&categorical_id, &user, &token.address);
+    assert_eq!(result, Err(Ok(Error::PredictionCancelled)));
+    let result = client.try_resolve_scalar(&admin, &scalar_id, &100_000);
+    assert_eq!(result, Err(Ok(Error::PredictionCancelled)));
+    let result = client.try_cancel_scalar(&admin, &scalar_id);
+    assert_eq!(result, Err(Ok(Error::PredictionCancelled)));
+}
+
+#[test]
+fn test_resolve_invalid_refunds_stakes() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will the final be played on Sunday?");
+    let unlock_time = 1000000 + 7200;
+
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user, &false, &250_0000000, &token.address);
+
+    let result = client.try_resolve_invalid(&admin, &prediction_id);
+    assert_eq!(result, Err(Ok(Error::PredictionNotLocked)));
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_resolve_invalid(&user, &prediction_id);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+
+    client.lock(&prediction_id);
+    client.resolve_invalid(&admin, &prediction_id);
+    let prediction = client.get_prediction(&prediction_id);
+    assert_eq!(prediction.status, PredictionStatus::Resolved);
+    assert_eq!(prediction.winner, Resolution::Invalid);
+
+    let result = client.try_resolve(&admin, &prediction_id, &true);
+    assert_eq!(result, Err(Ok(Error::AlreadyResolved)));
+
+    // Both sides get their own stake back with no platform fee
+    assert_eq!(
+        client.claim(&prediction_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(
+        client.claim(&prediction_id, &user, &token.address),
+        250_0000000
+    );
+    assert_eq!(token.balance(&user), 1000_0000000);
+    assert_eq!(
+        client.get_treasury(&token.address),
+        (DEFAULT_CREATION_FEE * 7) / 10
+    );
+}
+
+#[test]
+fn test_resolve_categorical_and_scalar_invalid() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let unlock_time = 1000000 + 7200;
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    let categorical_id = client.create_categorical(
+        &creator,
+        &String::from_str(&env, "Which team wins the league?"),
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    let scalar_id = client.create_scalar(
+        &creator,
+        &String::from_str(&env, "Where will BTC close the year?"),
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake_outcome(&categorical_id, &user, &1, &200_0000000, &token.address);
+    client.stake_scalar(&scalar_id, &user, &false, &300_0000000, &token.address);
+
+    let result = client.try_resolve_outcome_invalid(&admin, &categorical_id);
+    assert_eq!(result, Err(Ok(Error::ResolveTooEarly)));
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_resolve_scalar_invalid(&creator, &scalar_id);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+
+    client.resolve_outcome_invalid(&admin, &categorical_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &OutcomeResolvedInvalid {
+            prediction_id: categorical_id,
+            resolver: admin.clone(),
+            pools: vec![&env, DEFAULT_MIN_STAKE, 200_0000000],
+        },
+    );
+    client.resolve_scalar_invalid(&admin, &scalar_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &ScalarResolvedInvalid {
+            prediction_id: scalar_id,
+            resolver: admin.clone(),
+            long_pool: DEFAULT_MIN_STAKE,
+            short_pool: 300_0000000,
+        },
+    );
+
+    let result = client.try_resolve_outcome(&admin, &categorical_id, &1);
+    assert_eq!(result, Err(Ok(Error::AlreadyResolved)));
+    let result = client.try_resolve_scalar(&admin, &scalar_id, &100_000);
+    assert_eq!(result, Err(Ok(Error::AlreadyResolved)));
+
+    // Every stake comes back in full and no fee is taken
+    assert_eq!(
+        client.claim_outcome(&categorical_id, &user, &token.address),
+        200_0000000
+    );
+    assert_eq!(
+        client.claim_outcome(&categorical_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(
+        client.claim_scalar(&scalar_id, &user, &token.address),
+        300_0000000
+    );
+    assert_eq!(
+        client.claim_scalar(&scalar_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(token.balance(&user), 1000_0000000);
+    assert_eq!(
+        client.get_treasury(&token.address),
+        2 * ((DEFAULT_CREATION_FEE * 7) / 10)
+    );
+}
+
+#[test]
+fn test_resolve_empty_winning_side_refunds() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    // Everyone is on YES
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user, &true, &250_0000000, &token.address);
+
+    // Creator alone, nobody else staked
+    let lonely_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &false,
+        &token.address,
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // NO wins but nobody staked on NO: switch to refunds
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &false);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &ResolvedInvalid {
+            prediction_id,
+            resolver: admin.clone(),
+            yes_pool: DEFAULT_MIN_STAKE + 250_0000000,
+            no_pool: 0,
+        },
+    );
+    assert_eq!(
+        client.get_prediction(&prediction_id).winner,
+        Resolution::Invalid
+    );
+    assert_eq!(
+        client.claim(&prediction_id, &user, &token.address),
+        250_0000000
+    );
+    assert_eq!(
+        client.claim(&prediction_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+
+    // The creator's side wins but there is nobody to pay from
+    client.lock(&lonely_id);
+    client.resolve(&admin, &lonely_id, &false);
+    assert_eq!(
+        client.get_prediction(&lonely_id).winner,
+        Resolution::Invalid
+    );
+    assert_eq!(
+        client.claim(&lonely_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+
+    assert_eq!(token.balance(&user), 1000_0000000);
+    assert_eq!(
+        token.balance(&creator),
+        1000_0000000 - 2 * DEFAULT_CREATION_FEE
+    );
+}
+
+#[test]
+fn test_categorical_empty_winning_outcome_refunds() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Which team wins the league?");
+    let unlock_time = 1000000 + 7200;
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+        String::from_str(&env, "Greens"),
+    ];
+
+    let prediction_id = client.create_categorical(
+        &creator,
+        &question,
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    client.stake_outcome(&prediction_id, &user, &1, &200_0000000, &token.address);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Nobody backed the Greens
+    client.resolve_outcome(&admin, &prediction_id, &2);
+
+    assert_eq!(
+        client.claim_outcome(&prediction_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(
+        client.claim_outcome(&prediction_id, &user, &token.address),
+        200_0000000
+    );
+}
+
+#[test]
+fn test_scalar_one_sided_refunds() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "BTC price at end of year?");
+    let unlock_time = 1000000 + 7200;
+
+    // Only the creator's initial stake
+    let creator_only_id = client.create_scalar(
+        &creator,
+        &question,
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    // Everyone went LONG
+    let one_sided_id = client.create_scalar(
+        &creator,
+        &question,
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake_scalar(&one_sided_id, &user, &true, &200_0000000, &token.address);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let treasury = client.get_treasury(&token.address);
+    client.resolve_scalar(&admin, &creator_only_id, &120_000);
+    client.resolve_scalar(&admin, &one_sided_id, &60_000);
+
+    // No fee is taken and every stake comes back in full
+    let prediction = client.get_scalar(&one_sided_id);
+    assert_eq!(prediction.outcome, Some(60_000));
+    assert_eq!(prediction.settlement.platform_fee, 0);
+    assert_eq!(client.get_treasury(&token.address), treasury);
+
+    assert_eq!(
+        client.claim_scalar(&creator_only_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(
+        client.claim_scalar(&one_sided_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(
+        client.claim_scalar(&one_sided_id, &user, &token.address),
+        200_0000000
+    );
+    assert_eq!(token.balance(&user), 1000_0000000);
+    assert_eq!(
+        token.balance(&creator),
+        1000_0000000 - 2 * DEFAULT_CREATION_FEE
+    );
+}
+
+#[test]
+fn test_fee_settled_once_and_claims_add_up() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let user3 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    for user in [&creator, &user1, &user2, &user3] {
+        token_admin.mint(user, &1000_0000000);
+    }
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    // Odd stakes so the pro-rata shares do not divide evenly
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user1, &true, &100_0000001, &token.address);
+    client.stake(&prediction_id, &user2, &true, &100_0000002, &token.address);
+    client.stake(&prediction_id, &user3, &false, &100_0000003, &token.address);
+    let total_pool = 400_0000006;
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let creation_share = client.get_treasury(&token.address);
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &true);
+
+    // The fee is credited once, at resolution
+    let settlement = client.get_prediction(&prediction_id).settlement;
+    assert_eq!(settlement.platform_fee, total_pool - total_pool * 95 / 100);
+    assert_eq!(settlement.eligible_stake, 300_0000003);
+    let treasury = creation_share + settlement.platform_fee;
+    assert_eq!(client.get_treasury(&token.address), treasury);
+
+    let mut paid_out = client.claim(&prediction_id, &creator, &token.address);
+    paid_out += client.claim(&prediction_id, &user1, &token.address);
+    assert_eq!(client.get_treasury(&token.address), treasury);
+    paid_out += client.claim(&prediction_id, &user2, &token.address);
+
+    // The last claim sweeps the rounding dust
+    let settlement = client.get_prediction(&prediction_id).settlement;
+    assert!(settlement.dust > 0);
+    assert_eq!(settlement.paid_out, paid_out);
+    assert_eq!(
+        paid_out + settlement.platform_fee + settlement.dust,
+        total_pool
+    );
+    assert_eq!(
+        client.get_treasury(&token.address),
+        treasury + settlement.dust
+    );
+
+    // Only the treasury and the burn share of the creation fee are left
+    assert_eq!(
+        token.balance(&contract_id),
+        client.get_treasury(&token.address) + DEFAULT_CREATION_FEE - creation_share
+    );
+}
+
+#[test]
+fn test_config() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    assert_eq!(client.get_config(), Config::default());
+
+    let config = Config {
+        creation_fee: 10_0000000,
+        min_stake: 20_0000000,
+        min_lead_time: 60,
+        platform_fee_bps: 1000,
+        treasury_share_bps: 10_000,
+        resolution_window: 7 * 24 * 3600,
+        exit_penalty_bps: 1000,
+        penalty_treasury_share_bps: 0,
+    };
+
+    // Only the admin can change the config, and only within bounds
+    assert_eq!(
+        client.try_set_config(&creator, &config),
+        Err(Ok(Error::Unauthorized))
+    );
+    let too_greedy = Config {
+        platform_fee_bps: 1001,
+        ..config.clone()
+    };
+    assert_eq!(
+        client.try_set_config(&admin, &too_greedy),
+        Err(Ok(Error::InvalidConfig))
+    );
+    let free_stakes = Config {
+        min_stake: 0,
+        ..config.clone()
+    };
+    assert_eq!(
+        client.try_set_config(&admin, &free_stakes),
+        Err(Ok(Error::InvalidConfig))
+    );
+    let no_window = Config {
+        resolution_window: 60,
+        ..config.clone()
+    };
+    assert_eq!(
+        client.try_set_config(&admin, &no_window),
+        Err(Ok(Error::InvalidConfig))
+    );
+
+    client.set_config(&admin, &config);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &ConfigUpdated {
+            config_manager: admin.clone(),
+            config: config.clone(),
+        },
+    );
+    assert_eq!(client.get_config(), config);
+
+    // New markets use the new lead time, fee, minimum stake and treasury share
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 120;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    assert_eq!(token.balance(&creator), 1000_0000000 - 30_0000000);
+    assert_eq!(client.get_treasury(&token.address), 10_0000000);
+
+    client.stake(&prediction_id, &user, &false, &20_0000000, &token.address);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // 10% of the 40 XLM pool goes to the treasury
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &true);
+    assert_eq!(
+        client
+            .get_prediction(&prediction_id)
+            .settlement
+            .platform_fee,
+        4_0000000
+    );
+    assert_eq!(
+        client.claim(&prediction_id, &creator, &token.address),
+        36_0000000
+    );
+}
+
+#[test]
+fn test_admin_handover() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let new_admin = Address::generate(&env);
+    let stranger = Address::generate(&env);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    assert_eq!(
+        client.try_accept_admin(&new_admin),
+        Err(Ok(Error::NoPendingAdmin))
+    );
+    assert_eq!(
+        client.try_propose_admin(&stranger, &stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    client.propose_admin(&admin, &new_admin);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &AdminProposed {
+            admin: admin.clone(),
+            new_admin: new_admin.clone(),
+        },
+    );
+
+    // The old admin stays in charge until the proposal is accepted
+    assert_eq!(client.get_admin(), admin);
+    assert_eq!(client.get_pending_admin(), Some(new_admin.clone()));
+    assert_eq!(
+        client.try_accept_admin(&stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    client.accept_admin(&new_admin);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &AdminTransferred {
+            previous_admin: admin.clone(),
+            new_admin: new_admin.clone(),
+        },
+    );
+    assert_eq!(client.get_admin(), new_admin);
+    assert_eq!(client.get_pending_admin(), None);
+
+    assert_eq!(
+        client.try_propose_admin(&admin, &admin),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert!(client.has_role(&Role::Treasurer, &new_admin));
+    assert!(!client.has_role(&Role::Treasurer, &admin));
+}
+
+#[test]
+fn test_roles() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let resolver = Address::generate(&env);
+    let treasurer = Address::generate(&env);
+    let creator = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Only the admin manages roles
+    assert_eq!(
+        client.try_grant_role(&resolver, &Role::Resolver, &resolver),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    client.grant_role(&admin, &Role::Resolver, &resolver);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &RoleGranted {
+            role: Role::Resolver,
+            account: resolver.clone(),
+            admin: admin.clone(),
+        },
+    );
+    client.grant_role(&admin, &Role::Treasurer, &treasurer);
+
+    // Each role only unlocks its own operations
+    assert!(client.has_role(&Role::Resolver, &resolver));
+    assert!(!client.has_role(&Role::Treasurer, &resolver));
+    assert_eq!(
+        client.try_withdraw_treasury(&resolver, &1, &token.address),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        client.try_resolve(&treasurer, &prediction_id, &true),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(
+        client.try_set_config(&resolver, &Config::default()),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    client.lock(&prediction_id);
+    client.resolve(&resolver, &prediction_id, &true);
+    client.withdraw_treasury(&treasurer, &10_0000000, &token.address);
+    assert_eq!(token.balance(&treasurer), 10_0000000);
+
+    client.revoke_role(&admin, &Role::Treasurer, &treasurer);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &RoleRevoked {
+            role: Role::Treasurer,
+            account: treasurer.clone(),
+            admin: admin.clone(),
+        },
+    );
+    assert_eq!(
+        client.try_withdraw_treasury(&treasurer, &1, &token.address),
+        Err(Ok(Error::Unauthorized))
+    );
+}
+
+#[test]
+fn test_migrate_legacy_layout() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+    let loser = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+
+    let random_hash = BytesN::from_array(&env, &[7; 32]);
+    assert_eq!(
+        client.try_upgrade(&creator, &random_hash),
+        Err(Ok(Error::Unauthorized))
+    );
+
+    // Simulate a version 1 contract: a resolved market where YES won and
+    // the creator already claimed, plus the untyped treasury
+    let prediction = LegacyPrediction {
+        id: 1,
+        creator: creator.clone(),
+        question: String::from_str(&env, "Will Bitcoin hit $100k?"),
+        unlock_time: 1000000 + 7200,
+        yes_pool: 200_0000000,
+        no_pool: 100_0000000,
+        status: PredictionStatus::Resolved,
+        winner: Some(true),
+    };
+    let stakes = [
+        Stake {
+            user: creator.clone(),
+            choice: true,
+            amount: 100_0000000,
+            claimed: true,
+        },
+        Stake {
+            user: user.clone(),
+            choice: true,
+            amount: 100_0000000,
+            claimed: false,
+        },
+        Stake {
+            user: loser.clone(),
+            choice: false,
+            amount: 100_0000000,
+            claimed: false,
+        },
+    ];
+    env.as_contract(&contract_id, || {
+        let storage = env.storage().instance();
+        storage.remove(&DataKey::SchemaVersion);
+        storage.remove(&DataKey::Config);
+        storage.set(&DataKey::PredictionCount, &1u64);
+        storage.set(&DataKey::Prediction(1), &prediction);
+        for stake in stakes.iter() {
+            storage.set(&DataKey::UserStake(1, stake.user.clone()), stake);
+        }
+        storage.set(&vec![&env, Symbol::new(&env, "Treasury")], &35_0000000i128);
+    });
+    token_admin.mint(&contract_id, &(35_0000000 + 142_5000000));
+
+    assert_eq!(client.get_schema_version(), 1);
+    assert_eq!(
+        client.try_get_prediction(&1),
+        Err(Ok(Error::PredictionNotFound))
+    );
+    let users = vec![&env, creator.clone(), user.clone(), loser.clone()];
+    assert_eq!(
+        client.try_migrate_prediction(&admin, &1, &users),
+        Err(Ok(Error::MigrationRequired))
+    );
+
+    assert_eq!(client.migrate(&admin, &token.address), SCHEMA_VERSION);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Migrated {
+            admin: admin.clone(),
+            from_version: 1,
+            to_version: SCHEMA_VERSION,
+        },
+    );
+    assert_eq!(client.get_schema_version(), SCHEMA_VERSION);
+    assert_eq!(client.get_treasury(&token.address), 35_0000000);
+    assert_eq!(client.get_config(), Config::default());
+
+    assert_eq!(client.migrate_prediction(&admin, &1, &users), 3);
+
+    let migrated = client.get_prediction(&1);
+    assert_eq!(migrated.token, token.address);
+    assert_eq!(migrated.winner, Resolution::Yes);
+    assert_eq!(migrated.settlement.prize_pool, 285_0000000);
+    assert_eq!(migrated.settlement.claimed_stake, 100_0000000);
+    assert_eq!(migrated.settlement.paid_out, 142_5000000);
+    assert_eq!(
+        client
+            .get_user_positions(&user, &0, &10)
+            .get_unchecked(0)
+            .claimable,
+        142_5000000
+    );
+
+    // The remaining winner claims the same share as under version 1
+    assert_eq!(client.claim(&1, &user, &token.address), 142_5000000);
+    assert_eq!(
+        client.try_claim(&1, &loser, &token.address),
+        Err(Ok(Error::LostPrediction))
+    );
+    assert_eq!(
+        client.try_migrate(&admin, &token.address),
+        Ok(Ok(SCHEMA_VERSION))
+    );
+}
+
+#[test]
+fn test_migrate_prediction_across_calls() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    // A version 1 market where YES won and the creator already claimed
+    let prediction = LegacyPrediction {
+        id: 1,
+        creator: creator.clone(),
+        question: String::from_str(&env, "Will Bitcoin hit $100k?"),
+        unlock_time: 1000000 + 7200,
+        yes_pool: 200_0000000,
+        no_pool: 100_0000000,
+        status: PredictionStatus::Resolved,
+        winner: Some(true),
+    };
+    let stakes = [
+        Stake {
+            user: creator.clone(),
+            choice: true,
+            amount: 100_0000000,
+            claimed: true,
+        },
+        Stake {
+            user: user.clone(),
+            choice: true,
+            amount: 100_0000000,
+            claimed: false,
+        },
+    ];
+    env.as_contract(&contract_id, || {
+        let storage = env.storage().instance();
+        storage.remove(&DataKey::SchemaVersion);
+        storage.set(&DataKey::PredictionCount, &1u64);
+        storage.set(&DataKey::Prediction(1), &prediction);
+        for stake in stakes.iter() {
+            storage.set(&DataKey::UserStake(1, stake.user.clone()), stake);
+        }
+    });
+    token_admin.mint(&contract_id, &142_5000000);
+    client.migrate(&admin, &token.address);
+
+    // The first call converts the prediction, the second moves the claimed
+    // stake, which must still be indexed and counted
+    assert_eq!(
+        client.migrate_prediction(&admin, &1, &vec![&env, user.clone()]),
+        1
+    );
+    assert_eq!(client.get_prediction(&1).settlement.claimed_stake, 0);
+    assert_eq!(
+        client.migrate_prediction(&admin, &1, &vec![&env, creator.clone()]),
+        1
+    );
+
+    let migrated = client.get_prediction(&1);
+    assert_eq!(migrated.settlement.claimed_stake, 100_0000000);
+    assert_eq!(migrated.settlement.paid_out, 142_5000000);
+    assert_eq!(client.get_user_position_count(&creator), 1);
+    assert_eq!(client.get_user_position_count(&user), 1);
+
+    // Moving the same stakes again changes nothing
+    assert_eq!(
+        client.migrate_prediction(&admin, &1, &vec![&env, creator.clone(), user.clone()]),
+        0
+    );
+    assert_eq!(
+        client.get_prediction(&1).settlement.claimed_stake,
+        100_0000000
+    );
+    assert_eq!(client.get_user_position_count(&creator), 1);
+
+    // The last winner's claim sweeps the dust, so the books balance
+    assert_eq!(client.claim(&1, &user, &token.address), 142_5000000);
+    let settled = client.get_prediction(&1).settlement;
+    assert_eq!(settled.claimed_stake, settled.eligible_stake);
+    assert_eq!(settled.dust, 0);
+}
+
+#[test]
+fn test_migrate_prediction_sweeps_dust() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    // A version 1 market where YES won and every winner already claimed
+    let prediction = LegacyPrediction {
+        id: 1,
+        creator: creator.clone(),
+        question: String::from_str(&env, "Will Bitcoin hit $100k?"),
+        unlock_time: 1000000 + 7200,
+        yes_pool: 300_0000000,
+        no_pool: 100_0000000,
+        status: PredictionStatus::Resolved,
+        winner: Some(true),
+    };
+    let stakes = [
+        Stake {
+            user: creator.clone(),
+            choice: true,
+            amount: 100_0000000,
+            claimed: true,
+        },
+        Stake {
+            user: user.clone(),
+            choice: true,
+            amount: 200_0000000,
+            claimed: true,
+        },
+    ];
+    env.as_contract(&contract_id, || {
+        let storage = env.storage().instance();
+        storage.remove(&DataKey::SchemaVersion);
+        storage.set(&DataKey::PredictionCount, &1u64);
+        storage.set(&DataKey::Prediction(1), &prediction);
+        for stake in stakes.iter() {
+            storage.set(&DataKey::UserStake(1, stake.user.clone()), stake);
+        }
+    });
+    // Version 1 left the rounding dust in the contract
+    token_admin.mint(&contract_id, &1);
+    client.migrate(&admin, &token.address);
+    assert_eq!(client.get_treasury(&token.address), 0);
+
+    assert_eq!(
+        client.migrate_prediction(&admin, &1, &vec![&env, creator.clone(), user.clone()]),
+        2
+    );
+
+    // Prize pool = 380: 126.6666666 + 253.3333333 were paid, 1 unit is left
+    let settled = client.get_prediction(&1).settlement;
+    assert_eq!(settled.claimed_stake, settled.eligible_stake);
+    assert_eq!(settled.paid_out, 379_9999999);
+    assert_eq!(settled.dust, 1);
+    assert_eq!(client.get_treasury(&token.address), 1);
+    client.withdraw_treasury(&admin, &1, &token.address);
+}
+
+#[test]
+fn test_pause_operations() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let pauser = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    assert_eq!(
+        client.try_pause(&pauser, &Operation::Stake),
+        Err(Ok(Error::Unauthorized))
+    );
+    client.grant_role(&admin, &Role::Pauser, &pauser);
+
+    client.pause(&pauser, &Operation::Stake);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Paused {
+            operation: Operation::Stake,
+            pauser: pauser.clone(),
+        },
+    );
+    assert!(client.is_paused(&Operation::Stake));
+    assert!(!client.is_paused(&Operation::Create));
+    assert_eq!(client.get_paused_since(&Operation::Stake), Some(1000000));
+
+    // Only the paused operation is halted
+    assert_eq!(
+        client.try_stake(&prediction_id, &user, &false, &200_0000000, &token.address),
+        Err(Ok(Error::OperationPaused))
+    );
+    client.create_prediction(
+        &user,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    client.unpause(&pauser, &Operation::Stake);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Unpaused {
+            operation: Operation::Stake,
+            pauser: pauser.clone(),
+        },
+    );
+    client.stake(&prediction_id, &user, &false, &200_0000000, &token.address);
+
+    client.pause(&pauser, &Operation::Withdraw);
+    assert_eq!(
+        client.try_withdraw_treasury(&admin, &1, &token.address),
+        Err(Ok(Error::OperationPaused))
+    );
+}
+
+#[test]
+fn test_emergency_withdraw_after_long_pause() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user, &false, &200_0000000, &token.address);
+
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    let categorical_id = client.create_categorical(
+        &creator,
+        &question,
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    client.stake_outcome(&categorical_id, &user, &1, &200_0000000, &token.address);
+
+    // Not available while resolution is running
+    assert_eq!(
+        client.try_emergency_withdraw(&prediction_id, &user, &token.address),
+        Err(Ok(Error::EmergencyNotAvailable))
+    );
+
+    client.pause(&admin, &Operation::Resolve);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    assert_eq!(
+        client.try_resolve(&admin, &prediction_id, &true),
+        Err(Ok(Error::OperationPaused))
+    );
+    assert_eq!(
+        client.try_emergency_withdraw(&prediction_id, &user, &token.address),
+        Err(Ok(Error::EmergencyNotAvailable))
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000 + EMERGENCY_DELAY,
+        protocol_version: 23,
+        sequence_number: 30,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    assert_eq!(
+        client.emergency_withdraw(&prediction_id, &user, &token.address),
+        200_0000000
+    );
+    assert_last_event(
+        &env,
+        &contract_id,
+        &EmergencyWithdrawn {
+            prediction_id,
+            user: user.clone(),
+            amount: 200_0000000,
+        },
+    );
+    assert_eq!(
+        client.try_emergency_withdraw(&prediction_id, &user, &token.address),
+        Err(Ok(Error::AlreadyClaimed))
+    );
+    assert_eq!(
+        client.emergency_withdraw_outcome(&categorical_id, &user, &token.address),
+        200_0000000
+    );
+    assert_eq!(token.balance(&user), 1000_0000000);
+
+    // The pools no longer include the withdrawn stakes
+    let prediction = client.get_prediction(&prediction_id);
+    assert_eq!(prediction.no_pool, 0);
+    assert_eq!(
+        client.get_categorical(&categorical_id).pools,
+        vec![&env, DEFAULT_MIN_STAKE, 0]
+    );
+
+    // The pause outlasted the resolution deadline, which was extended by the
+    // time resolution was paused, so the market cannot expire yet
+    client.unpause(&admin, &Operation::Resolve);
+    client.lock(&prediction_id);
+    assert_eq!(
+        client.try_expire(&prediction_id),
+        Err(Ok(Error::DeadlineNotReached))
+    );
+
+    // Once the extended deadline passes the remaining stake is refunded
+    // rather than resolved
+    env.ledger().set(LedgerInfo {
+        timestamp: prediction.resolve_deadline + EMERGENCY_DELAY,
+        protocol_version: 23,
+        sequence_number: 40,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    assert_eq!(
+        client.try_resolve(&admin, &prediction_id, &true),
+        Err(Ok(Error::ResolutionExpired))
+    );
+    client.expire(&prediction_id);
+    assert_eq!(
+        client.refund(&prediction_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+}
+
+#[test]
+fn test_list_predictions() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &2000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let soon = 1000000 + 7200;
+    let later = 1000000 + 72000;
+
+    // Ids 1 and 4 unlock soon, 3 later, 2 is categorical, 5 gets cancelled
+    let resolved_id =
+        client.create_prediction(&creator, &question, &soon, &soon, &true, &token.address);
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    client.create_categorical(&creator, &question, &outcomes, &soon, &0, &token.address);
+    let open_id =
+        client.create_prediction(&creator, &question, &later, &later, &true, &token.address);
+    let waiting_id =
+        client.create_prediction(&user, &question, &soon, &soon, &false, &token.address);
+    let cancelled_id =
+        client.create_prediction(&user, &question, &later, &later, &true, &token.address);
+    client.stake(&resolved_id, &user, &false, &200_0000000, &token.address);
+    client.cancel_prediction(&user, &cancelled_id);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: soon + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    client.lock(&resolved_id);
+    client.resolve(&admin, &resolved_id, &true);
+
+    let ids = |page: soroban_sdk::Vec<Prediction>| {
+        let mut ids = vec![&env];
+        for prediction in page.iter() {
+            ids.push_back(prediction.id);
+        }
+        ids
+    };
+
+    // Categorical markets share the id space but are not listed
+    assert_eq!(
+        ids(client.list_predictions(&0, &10, &StatusFilter::All)),
+        vec![&env, 1u64, 3, 4, 5]
+    );
+    assert_eq!(
+        ids(client.list_predictions(&1, &10, &StatusFilter::Open)),
+        vec![&env, open_id]
+    );
+    assert_eq!(
+        ids(client.list_predictions(&1, &10, &StatusFilter::AwaitingResolution)),
+        vec![&env, waiting_id]
+    );
+    assert_eq!(
+        ids(client.list_predictions(&1, &10, &StatusFilter::Resolved)),
+        vec![&env, resolved_id]
+    );
+    assert_eq!(
+        ids(client.list_predictions(&1, &10, &StatusFilter::Cancelled)),
+        vec![&env, cancelled_id]
+    );
+
+    // Pages walk the id window
+    assert_eq!(
+        ids(client.list_predictions(&1, &2, &StatusFilter::All)),
+        vec![&env, 1u64]
+    );
+    assert_eq!(
+        ids(client.list_predictions(&3, &2, &StatusFilter::All)),
+        vec![&env, 3u64, 4]
+    );
+    assert_eq!(client.list_predictions(&6, &2, &StatusFilter::All).len(), 0);
+}
+
+#[test]
+fn test_user_positions() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    let binary_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    let categorical_id = client.create_categorical(
+        &creator,
+        &question,
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    let scalar_id = client.create_scalar(
+        &creator,
+        &question,
+        &0,
+        &100,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&binary_id, &user, &false, &100_0000000, &token.address);
+    client.stake_outcome(&categorical_id, &user, &1, &100_0000000, &token.address);
+    client.stake_scalar(&scalar_id, &user, &false, &100_0000000, &token.address);
+
+    assert_eq!(client.get_user_position_count(&creator), 3);
+    assert_eq!(client.get_user_position_count(&user), 3);
+    assert_eq!(client.get_user_position_count(&admin), 0);
+
+    let positions = client.get_user_positions(&user, &0, &10);
+    assert_eq!(
+        positions.get_unchecked(0),
+        Position {
+            prediction_id: binary_id,
+            kind: MarketKind::Binary,
+            side: 0,
+            amount: 100_0000000,
+            claimed: false,
+            status: PredictionStatus::Open,
+            claimable: 0,
+        }
+    );
+    assert_eq!(positions.get_unchecked(1).kind, MarketKind::Categorical);
+    assert_eq!(positions.get_unchecked(1).side, 1);
+    assert_eq!(positions.get_unchecked(2).kind, MarketKind::Scalar);
+
+    // Pages are windows over the index
+    let page = client.get_user_positions(&user, &1, &1);
+    assert_eq!(page.len(), 1);
+    assert_eq!(page.get_unchecked(0).prediction_id, categorical_id);
+    assert_eq!(client.get_user_positions(&user, &3, &10).len(), 0);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // The user wins the binary market, loses the categorical one and takes
+    // the SHORT side of a scalar market resolved at 25
+    client.lock(&binary_id);
+    client.resolve(&admin, &binary_id, &false);
+    client.resolve_outcome(&admin, &categorical_id, &0);
+    client.resolve_scalar(&admin, &scalar_id, &25);
+
+    let positions = client.get_user_positions(&user, &0, &10);
+    assert_eq!(
+        positions.get_unchecked(0).status,
+        PredictionStatus::Resolved
+    );
+    assert_eq!(positions.get_unchecked(0).claimable, 190_0000000);
+    assert_eq!(positions.get_unchecked(1).claimable, 0);
+    assert_eq!(positions.get_unchecked(2).claimable, 142_5000000);
+
+    client.claim(&binary_id, &user, &token.address);
+    let claimed = client.get_user_positions(&user, &0, &1).get_unchecked(0);
+    assert!(claimed.claimed);
+    assert_eq!(claimed.claimable, 0);
+
+    // A missing index entry, e.g. one that was archived, is skipped
+    env.as_contract(&contract_id, || {
+        env.storage()
+            .persistent()
+            .remove(&DataKey::UserPosition(user.clone(), 1));
+    });
+    let positions = client.get_user_positions(&user, &0, &10);
+    assert_eq!(positions.len(), 2);
+    assert_eq!(positions.get_unchecked(1).prediction_id, scalar_id);
+}
+
+#[test]
+fn test_quote_and_claimable() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+
+    // Nobody on NO yet, so staking YES could only be refunded
+    assert_eq!(
+        client.quote_stake(&prediction_id, &true, &200_0000000),
+        200_0000000
+    );
+    assert_eq!(
+        client.try_quote_stake(&prediction_id, &false, &1),
+        Err(Ok(Error::StakeTooLow))
+    );
+
+    // 100 YES, then 300 NO: a winning NO stake takes 95% of the 400 pool
+    let quote = client.quote_stake(&prediction_id, &false, &300_0000000);
+    assert_eq!(quote, 380_0000000);
+    client.stake(&prediction_id, &user1, &false, &300_0000000, &token.address);
+    client.stake(&prediction_id, &user2, &true, &100_0000000, &token.address);
+
+    assert_eq!(
+        client.get_claimable(&prediction_id, &user1),
+        Claimable {
+            amount: 0,
+            reason: Error::NotResolved as u32,
+        }
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    assert_eq!(
+        client.try_quote_stake(&prediction_id, &true, &100_0000000),
+        Err(Ok(Error::PredictionExpired))
+    );
+
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &false);
+
+    // The later YES stake diluted the pool the quote was based on
+    let claimable = client.get_claimable(&prediction_id, &user1);
+    assert_eq!(claimable.reason, 0);
+    assert_eq!(claimable.amount, 475_0000000);
+    assert_eq!(
+        client.claim(&prediction_id, &user1, &token.address),
+        claimable.amount
+    );
+
+    assert_eq!(
+        client.get_claimable(&prediction_id, &user1),
+        Claimable {
+            amount: 0,
+            reason: Error::AlreadyClaimed as u32,
+        }
+    );
+    assert_eq!(
+        client.get_claimable(&prediction_id, &user2).reason,
+        Error::LostPrediction as u32
+    );
+    assert_eq!(
+        client.get_claimable(&prediction_id, &admin).reason,
+        Error::StakeNotFound as u32
+    );
+}
+
+#[test]
+fn test_claim_many() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+    let other = Address::generate(&env);
+
+    let (token_a, token_a_admin) = create_token_contract(&env, &admin);
+    let (token_b, token_b_admin) = create_token_contract(&env, &admin);
+    for account in [&creator, &user, &other] {
+        token_a_admin.mint(account, &1000_0000000);
+        token_b_admin.mint(account, &1000_0000000);
+    }
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+
+    // The creator backs YES in each market; the user wins 1 and 2 and loses 3
+    let won_a = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token_a.address,
+    );
+    let won_b = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token_b.address,
+    );
+    let lost = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token_a.address,
+    );
+    client.stake(&won_a, &user, &false, &100_0000000, &token_a.address);
+    client.stake(&won_b, &user, &false, &300_0000000, &token_b.address);
+    client.stake(&lost, &user, &false, &100_0000000, &token_a.address);
+    client.stake(&won_b, &other, &false, &100_0000000, &token_b.address);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.lock(&won_a);
+    client.resolve(&admin, &won_a, &false);
+    client.lock(&won_b);
+    client.resolve(&admin, &won_b, &false);
+    client.lock(&lost);
+    client.resolve(&admin, &lost, &true);
+
+    let payout_a = client.get_claimable(&won_a, &user).amount;
+    let payout_b = client.get_claimable(&won_b, &user).amount;
+    assert_eq!(payout_a, 190_0000000);
+    assert_eq!(payout_b, 356_2500000);
+
+    let balance_a = token_a.balance(&user);
+    let balance_b = token_b.balance(&user);
+
+    // Failed entries are reported and skipped, including a repeated id
+    let ids = vec![&env, won_a, lost, 99, won_b, won_a];
+    let batch = client.claim_many(&user, &ids);
+
+    let result = |prediction_id, payout, reason: u32| ClaimResult {
+        prediction_id,
+        payout,
+        reason,
+    };
+    assert_eq!(
+        batch.results,
+        vec![
+            &env,
+            result(won_a, payout_a, 0),
+            result(lost, 0, Error::LostPrediction as u32),
+            result(99, 0, Error::PredictionNotFound as u32),
+            result(won_b, payout_b, 0),
+            result(won_a, 0, Error::AlreadyClaimed as u32),
+        ]
+    );
+    assert_eq!(batch.totals.len(), 2);
+    assert_eq!(batch.totals.get(token_a.address.clone()), Some(payout_a));
+    assert_eq!(batch.totals.get(token_b.address.clone()), Some(payout_b));
+    assert_eq!(token_a.balance(&user), balance_a + payout_a);
+    assert_eq!(token_b.balance(&user), balance_b + payout_b);
+
+    // The other winner can still claim on their own
+    assert_eq!(client.claim(&won_b, &other, &token_b.address), 118_7500000);
+
+    let mut too_many = Vec::new(&env);
+    for prediction_id in 0..=MAX_BATCH_SIZE as u64 {
+        too_many.push_back(prediction_id);
+    }
+    assert_eq!(
+        client.try_claim_many(&user, &too_many),
+        Err(Ok(Error::BatchTooLarge))
+    );
+
+    client.pause(&admin, &Operation::Claim);
+    assert_eq!(
+        client.try_claim_many(&user, &ids),
+        Err(Ok(Error::OperationPaused))
+    );
+}
+
+#[test]
+fn test_claim_many_across_market_types() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let unlock_time = 1000000 + 7200;
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    let categorical_id = client.create_categorical(
+        &creator,
+        &String::from_str(&env, "Which team wins the league?"),
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    let scalar_id = client.create_scalar(
+        &creator,
+        &String::from_str(&env, "Where will BTC close the year?"),
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    let cancelled_id = client.create_prediction(
+        &creator,
+        &String::from_str(&env, "Will Bitcoin hit $100k?"),
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake_outcome(&categorical_id, &user, &1, &100_0000000, &token.address);
+    client.stake_scalar(&scalar_id, &user, &false, &100_0000000, &token.address);
+    client.stake(&cancelled_id, &user, &false, &100_0000000, &token.address);
+    client.cancel_prediction(&admin, &cancelled_id);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // The user wins the categorical market and takes SHORT's 75% of the
+    // scalar prize pool
+    client.resolve_outcome(&admin, &categorical_id, &1);
+    client.resolve_scalar(&admin, &scalar_id, &75_000);
+
+    let balance = token.balance(&user);
+    let ids = vec![&env, categorical_id, scalar_id, cancelled_id, scalar_id];
+    let batch = client.claim_many(&user, &ids);
+
+    let result = |prediction_id, payout, reason: u32| ClaimResult {
+        prediction_id,
+        payout,
+        reason,
+    };
+    assert_eq!(
+        batch.results,
+        vec![
+            &env,
+            result(categorical_id, 190_0000000, 0),
+            result(scalar_id, 142_5000000, 0),
+            result(cancelled_id, 100_0000000, 0),
+            result(scalar_id, 0, Error::AlreadyClaimed as u32),
+        ]
+    );
+    let total = 190_0000000 + 142_5000000 + 100_0000000;
+    assert_eq!(batch.totals.get(token.address.clone()), Some(total));
+    assert_eq!(token.balance(&user), balance + total);
+
+    // The losers are reported, and the creator's refund goes through too
+    let batch = client.claim_many(&creator, &ids);
+    assert_eq!(
+        batch.results,
+        vec![
+            &env,
+            result(categorical_id, 0, Error::LostPrediction as u32),
+            result(scalar_id, 47_5000000, 0),
+            result(cancelled_id, DEFAULT_MIN_STAKE, 0),
+            result(scalar_id, 0, Error::AlreadyClaimed as u32),
+        ]
+    );
+}
+
+#[test]
+fn test_read_older_layouts() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let (token, token_admin) = create_token_contract(&env, &admin);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    // A version 2 market already in persistent storage, with a single unlock
+    // time, and a config from before the resolution window and exit penalty
+    let unlock_time = 1000000 + 7200;
+    let current = Prediction {
+        id: 1,
+        creator: creator.clone(),
+        question: String::from_str(&env, "Will Bitcoin hit $100k?"),
+        token: token.address.clone(),
+        close_time: 0,
+        resolve_after: 0,
+        resolve_deadline: 0,
+        pause_offset: 0,
+        yes_pool: DEFAULT_MIN_STAKE,
+        no_pool: DEFAULT_MIN_STAKE,
+        forfeited: 0,
+        status: PredictionStatus::Open,
+        winner: Resolution::Pending,
+        settlement: Settlement::default(),
+    };
+    let mut old =
+        Map::<Symbol, Val>::try_from_val(&env, &IntoVal::<Env, Val>::into_val(&current, &env))
+            .unwrap();
+    for field in [
+        "close_time",
+        "resolve_after",
+        "resolve_deadline",
+        "pause_offset",
+        "forfeited",
+    ] {
+        old.remove(Symbol::new(&env, field));
+    }
+    old.set(Symbol::new(&env, "unlock_time"), unlock_time.into_val(&env));
+
+    let mut old_config = Map::<Symbol, Val>::try_from_val(
+        &env,
+        &IntoVal::<Env, Val>::into_val(&Config::default(), &env),
+    )
+    .unwrap();
+    for field in [
+        "resolution_window",
+        "exit_penalty_bps",
+        "penalty_treasury_share_bps",
+    ] {
+        old_config.remove(Symbol::new(&env, field));
+    }
+    old_config.set(Symbol::new(&env, "platform_fee_bps"), 300u32.into_val(&env));
+
+    env.as_contract(&contract_id, || {
+        let storage = env.storage().instance();
+        storage.set(&DataKey::PredictionCount, &1u64);
+        storage.set(&DataKey::Config, &old_config);
+        env.storage()
+            .persistent()
+            .set(&DataKey::Prediction(1), &old);
+    });
+
+    // Markets of the other types from before they had a resolution deadline
+    // take one from the current resolution window
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    token_admin.mint(&creator, &1000_0000000);
+    let categorical_id = client.create_categorical(
+        &creator,
+        &String::from_str(&env, "Which team wins the league?"),
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    let scalar_id = client.create_scalar(
+        &creator,
+        &String::from_str(&env, "Where will BTC close the year?"),
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    let lmsr_id = client.create_lmsr(
+        &creator,
+        &String::from_str(&env, "Will ETH reach $5k this year?"),
+        &unlock_time,
+        &100_0000000,
+        &token.address,
+    );
+    let cpmm_id = client.create_cpmm(
+        &creator,
+        &String::from_str(&env, "Will ETH reach $5k this year?"),
+        &unlock_time,
+        &100_0000000,
+        &200,
+        &token.address,
+    );
+    env.as_contract(&contract_id, || {
+        let storage = env.storage().persistent();
+        for key in [
+            DataKey::Categorical(categorical_id),
+            DataKey::Scalar(scalar_id),
+            DataKey::Lmsr(lmsr_id),
+            DataKey::Cpmm(cpmm_id),
+        ] {
+            let mut market: Map<Symbol, Val> = storage.get(&key).unwrap();
+            market.remove(Symbol::new(&env, "resolve_deadline"));
+            market.remove(Symbol::new(&env, "pause_offset"));
+            market.remove(Symbol::new(&env, "void_price"));
+            storage.set(&key, &market);
+        }
+    });
+    let categorical = client.get_categorical(&categorical_id);
+    assert_eq!(
+        categorical.resolve_deadline,
+        unlock_time + DEFAULT_RESOLUTION_WINDOW
+    );
+    assert_eq!(categorical.pause_offset, 0);
+    assert_eq!(
+        client.get_scalar(&scalar_id).resolve_deadline,
+        unlock_time + DEFAULT_RESOLUTION_WINDOW
+    );
+    assert_eq!(
+        client.get_lmsr(&lmsr_id).resolve_deadline,
+        unlock_time + DEFAULT_RESOLUTION_WINDOW
+    );
+    assert_eq!(
+        client.get_cpmm(&cpmm_id).resolve_deadline,
+        unlock_time + DEFAULT_RESOLUTION_WINDOW
+    );
+
+    let config = client.get_config();
+    assert_eq!(config.platform_fee_bps, 300);
+    assert_eq!(config.resolution_window, DEFAULT_RESOLUTION_WINDOW);
+    assert_eq!(config.exit_penalty_bps, DEFAULT_EXIT_PENALTY_BPS);
+
+    let prediction = client.get_prediction(&1);
+    assert_eq!(prediction.close_time, unlock_time);
+    assert_eq!(prediction.resolve_after, unlock_time);
+    assert_eq!(
+        prediction.resolve_deadline,
+        unlock_time + DEFAULT_RESOLUTION_WINDOW
+    );
+    assert_eq!(prediction.yes_pool, DEFAULT_MIN_STAKE);
+    assert_eq!(prediction.forfeited, 0);
+
+    // Locking rewrites it in the current layout
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    client.lock(&1);
+    env.as_contract(&contract_id, || {
+        let stored: Prediction = env
+            .storage()
+            .persistent()
+            .get(&DataKey::Prediction(1))
+            .unwrap();
+        assert_eq!(stored.status, PredictionStatus::Locked);
+        assert_eq!(stored.close_time, unlock_time);
+    });
+}
+
+#[test]
+fn test_expire_unresolved() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user, &false, &250_0000000, &token.address);
+
+    let deadline = client.get_prediction(&prediction_id).resolve_deadline;
+    assert_eq!(deadline, unlock_time + DEFAULT_RESOLUTION_WINDOW);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: deadline - 1,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // The resolver still has time
+    assert_eq!(
+        client.try_expire(&prediction_id),
+        Err(Ok(Error::DeadlineNotReached))
+    );
+    assert_eq!(
+        client.try_refund(&prediction_id, &user, &token.address),
+        Err(Ok(Error::NotCancelled))
+    );
+    client.lock(&prediction_id);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: deadline,
+        protocol_version: 23,
+        sequence_number: 30,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    assert_eq!(
+        client.try_resolve(&admin, &prediction_id, &true),
+        Err(Ok(Error::ResolutionExpired))
+    );
+
+    // Anyone can expire the market once the deadline has passed
+    client.expire(&prediction_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Expired {
+            prediction_id,
+            yes_pool: DEFAULT_MIN_STAKE,
+            no_pool: 250_0000000,
+        },
+    );
+    assert_eq!(
+        client.get_prediction(&prediction_id).status,
+        PredictionStatus::Expired
+    );
+    assert_eq!(
+        client.try_expire(&prediction_id),
+        Err(Ok(Error::ResolutionExpired))
+    );
+    assert_eq!(
+        client.try_claim(&prediction_id, &user, &token.address),
+        Err(Ok(Error::ResolutionExpired))
+    );
+    assert_eq!(
+        client.try_cancel_prediction(&admin, &prediction_id),
+        Err(Ok(Error::ResolutionExpired))
+    );
+
+    let positions = client.get_user_positions(&user, &0, &10);
+    assert_eq!(positions.get_unchecked(0).claimable, 250_0000000);
+    assert_eq!(
+        client
+            .list_predictions(&1, &10, &StatusFilter::Expired)
+            .len(),
+        1
+    );
+
+    // Every staker gets their original stake back
+    let balance = token.balance(&user);
+    assert_eq!(
+        client.refund(&prediction_id, &user, &token.address),
+        250_0000000
+    );
+    assert_eq!(token.balance(&user), balance + 250_0000000);
+    assert_eq!(
+        client.refund(&prediction_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(
+        client.try_refund(&prediction_id, &user, &token.address),
+        Err(Ok(Error::AlreadyClaimed))
+    );
+}
+
+#[test]
+fn test_expire_categorical_and_scalar() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let unlock_time = 1000000 + 7200;
+    let outcomes = vec![
+        &env,
+        String::from_str(&env, "Reds"),
+        String::from_str(&env, "Blues"),
+    ];
+    let categorical_id = client.create_categorical(
+        &creator,
+        &String::from_str(&env, "Which team wins the league?"),
+        &outcomes,
+        &unlock_time,
+        &0,
+        &token.address,
+    );
+    let scalar_id = client.create_scalar(
+        &creator,
+        &String::from_str(&env, "Where will BTC close the year?"),
+        &50_000,
+        &150_000,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake_outcome(&categorical_id, &user, &1, &250_0000000, &token.address);
+    client.stake_scalar(&scalar_id, &user, &false, &250_0000000, &token.address);
+
+    let deadline = unlock_time + DEFAULT_RESOLUTION_WINDOW;
+    assert_eq!(
+        client.get_categorical(&categorical_id).resolve_deadline,
+        deadline
+    );
+    assert_eq!(client.get_scalar(&scalar_id).resolve_deadline, deadline);
+    assert_eq!(
+        client.try_expire_outcome(&categorical_id),
+        Err(Ok(Error::DeadlineNotReached))
+    );
+
+    // Resolution is paused for a day after unlock, which pushes the deadline
+    // back by the same amount
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    client.pause(&admin, &Operation::Resolve);
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 86400,
+        protocol_version: 23,
+        sequence_number: 30,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    client.unpause(&admin, &Operation::Resolve);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: deadline,
+        protocol_version: 23,
+        sequence_number: 40,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    assert_eq!(
+        client.try_expire_outcome(&categorical_id),
+        Err(Ok(Error::DeadlineNotReached))
+    );
+    assert_eq!(
+        client.try_expire_scalar(&scalar_id),
+        Err(Ok(Error::DeadlineNotReached))
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: deadline + 86400,
+        protocol_version: 23,
+        sequence_number: 50,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    assert_eq!(
+        client.try_resolve_outcome(&admin, &categorical_id, &1),
+        Err(Ok(Error::ResolutionExpired))
+    );
+    assert_eq!(
+        client.try_resolve_scalar(&admin, &scalar_id, &100_000),
+        Err(Ok(Error::ResolutionExpired))
+    );
+
+    // Anyone can expire both markets once the extended deadline has passed
+    client.expire_outcome(&categorical_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &OutcomeExpired {
+            prediction_id: categorical_id,
+            pools: vec![&env, DEFAULT_MIN_STAKE, 250_0000000],
+        },
+    );
+    client.expire_scalar(&scalar_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &ScalarExpired {
+            prediction_id: scalar_id,
+            long_pool: DEFAULT_MIN_STAKE,
+            short_pool: 250_0000000,
+        },
+    );
+    assert_eq!(
+        client.try_expire_scalar(&scalar_id),
+        Err(Ok(Error::ResolutionExpired))
+    );
+    assert_eq!(
+        client.try_claim_outcome(&categorical_id, &user, &token.address),
+        Err(Ok(Error::ResolutionExpired))
+    );
+
+    // Every staker gets their original stake back
+    assert_eq!(
+        client.refund_outcome(&categorical_id, &user, &token.address),
+        250_0000000
+    );
+    assert_eq!(
+        client.refund_scalar(&scalar_id, &user, &token.address),
+        250_0000000
+    );
+    assert_eq!(
+        client.refund_outcome(&categorical_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(
+        client.refund_scalar(&scalar_id, &creator, &token.address),
+        DEFAULT_MIN_STAKE
+    );
+    assert_eq!(token.balance(&user), 1000_0000000);
+    assert_eq!(
+        client.try_refund_scalar(&scalar_id, &user, &token.address),
+        Err(Ok(Error::AlreadyClaimed))
+    );
+}
+
+#[test]
+fn test_top_up_stake() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user, &false, &200_0000000, &token.address);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000 + 600,
+        protocol_version: 23,
+        sequence_number: 15,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // The creator adds to the stake create_prediction made for them
+    client.stake(
+        &prediction_id,
+        &creator,
+        &true,
+        &150_0000000,
+        &token.address,
+    );
+    assert_last_event(
+        &env,
+        &contract_id,
+        &Staked {
+            prediction_id,
+            user: creator.clone(),
+            choice: true,
+            amount: 150_0000000,
+            total: 250_0000000,
+            yes_pool: 250_0000000,
+            no_pool: 200_0000000,
+        },
+    );
+    client.stake(&prediction_id, &user, &false, &100_0000000, &token.address);
+
+    let stake = client.get_stake(&prediction_id, &creator).unwrap();
+    assert_eq!(stake.amount, 250_0000000);
+    assert_eq!(
+        client.get_stake_history(&prediction_id, &creator),
+        vec![
+            &env,
+            StakeIncrement {
+                amount: DEFAULT_MIN_STAKE,
+                timestamp: 1000000,
+            },
+            StakeIncrement {
+                amount: 150_0000000,
+                timestamp: 1000000 + 600,
+            },
+        ]
+    );
+    assert_eq!(client.get_stake_history(&prediction_id, &user).len(), 2);
+
+    let prediction = client.get_prediction(&prediction_id);
+    assert_eq!(prediction.yes_pool, 250_0000000);
+    assert_eq!(prediction.no_pool, 300_0000000);
+
+    // Topping up keeps a single entry in the position index
+    assert_eq!(client.get_user_position_count(&user), 1);
+
+    // A user backs one side only
+    assert_eq!(
+        client.try_stake(&prediction_id, &user, &true, &100_0000000, &token.address),
+        Err(Ok(Error::CannotSwitchSides))
+    );
+    assert_eq!(
+        client.try_stake(&prediction_id, &user, &false, &1, &token.address),
+        Err(Ok(Error::StakeTooLow))
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // The whole accumulated stake is paid out: 95% of the 550 pool
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &false);
+    assert_eq!(
+        client.claim(&prediction_id, &user, &token.address),
+        522_5000000
+    );
+}
+
+#[test]
+fn test_withdraw_stake() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user1, &false, &200_0000000, &token.address);
+    client.stake(&prediction_id, &user2, &false, &300_0000000, &token.address);
+
+    // 5% of 300 is kept: half to the treasury, half to the pool
+    let treasury = client.get_treasury(&token.address);
+    let balance = token.balance(&user2);
+    assert_eq!(client.withdraw_stake(&prediction_id, &user2), 285_0000000);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &StakeWithdrawn {
+            prediction_id,
+            user: user2.clone(),
+            amount: 285_0000000,
+            penalty: 15_0000000,
+            yes_pool: DEFAULT_MIN_STAKE,
+            no_pool: 200_0000000,
+        },
+    );
+    assert_eq!(token.balance(&user2), balance + 285_0000000);
+    assert_eq!(client.get_treasury(&token.address), treasury + 7_5000000);
+    assert_eq!(client.get_prediction(&prediction_id).forfeited, 7_5000000);
+    assert_eq!(client.get_stake(&prediction_id, &user2), None);
+    assert_eq!(
+        client.try_withdraw_stake(&prediction_id, &user2),
+        Err(Ok(Error::StakeNotFound))
+    );
+
+    // Staking again starts a new stake without indexing the market twice
+    client.stake(&prediction_id, &user2, &false, &100_0000000, &token.address);
+    assert_eq!(client.get_user_position_count(&user2), 1);
+    let history = client.get_stake_history(&prediction_id, &user2);
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.get_unchecked(1).amount, -300_0000000);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    assert_eq!(
+        client.try_withdraw_stake(&prediction_id, &user1),
+        Err(Ok(Error::PredictionExpired))
+    );
+
+    // The winners share the forfeited part along with the pools
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &false);
+    let settlement = client.get_prediction(&prediction_id).settlement;
+    assert_eq!(
+        settlement.prize_pool + settlement.platform_fee,
+        400_0000000 + 7_5000000
+    );
+    assert_eq!(
+        client.claim(&prediction_id, &user1, &token.address),
+        200_0000000 * settlement.prize_pool / 300_0000000
+    );
+
+    // A market that ends without winners releases it to the treasury
+    let cancelled_id = client.create_prediction(
+        &creator,
+        &question,
+        &(unlock_time + 7200),
+        &(unlock_time + 7200),
+        &true,
+        &token.address,
+    );
+    client.stake(&cancelled_id, &user1, &false, &200_0000000, &token.address);
+    client.withdraw_stake(&cancelled_id, &user1);
+    let treasury = client.get_treasury(&token.address);
+    client.cancel_prediction(&admin, &cancelled_id);
+    assert_eq!(client.get_treasury(&token.address), treasury + 5_0000000);
+
+    // A creator who has withdrawn their own stake can still back out
+    let withdrawn_id = client.create_prediction(
+        &creator,
+        &question,
+        &(unlock_time + 7200),
+        &(unlock_time + 7200),
+        &true,
+        &token.address,
+    );
+    client.withdraw_stake(&withdrawn_id, &creator);
+    client.cancel_prediction(&creator, &withdrawn_id);
+    assert_eq!(
+        client.get_prediction(&withdrawn_id).status,
+        PredictionStatus::Cancelled
+    );
+    assert_eq!(
+        client.try_refund(&withdrawn_id, &creator, &token.address),
+        Err(Ok(Error::StakeNotFound))
+    );
+}
+
+#[test]
+fn test_fixed_point_math() {
+    use fixed::{exp_neg, ln_1p, LN_2, ONE};
+
+    assert_eq!(exp_neg(0), ONE);
+    assert_eq!(exp_neg(50 * ONE), 0);
+    assert_eq!(ln_1p(0), 0);
+
+    // Within a few dozen units in the last place of the exact values
+    assert!((exp_neg(LN_2) - ONE / 2).abs() <= 10);
+    assert!((exp_neg(ONE) - 367_879_441_171_442_321).abs() <= 10);
+    assert!((exp_neg(10 * ONE) - 45_399_929_762_484).abs() <= 10);
+    assert!((ln_1p(ONE) - LN_2).abs() <= 50);
+    assert!((ln_1p(ONE / 2) - 405_465_108_108_164_381).abs() <= 50);
+
+    // Strictly decreasing in x
+    let mut previous = ONE + 1;
+    for i in 0..64 {
+        let value = exp_neg(i * ONE / 2);
+        assert!(value < previous);
+        previous = value;
+    }
+}
+
+#[test]
+fn test_lmsr_prediction() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will ETH reach $5k this year?");
+    let unlock_time = 1000000 + 7200;
+
+    let result = client.try_create_lmsr(&creator, &question, &unlock_time, &1, &token.address);
+    assert_eq!(result, Err(Ok(Error::InvalidLiquidity)));
+
+    let prediction_id = client.create_lmsr(
+        &creator,
+        &question,
+        &unlock_time,
+        &100_0000000,
+        &token.address,
+    );
+
+    // Creator pays the creation fee plus b * ln 2, rounded up
+    let prediction = client.get_lmsr(&prediction_id);
+    assert_eq!(prediction.subsidy, 693147182);
+    assert_eq!(
+        token.balance(&creator),
+        1000_0000000 - 50_0000000 - prediction.subsidy
+    );
+    assert_eq!(
+        client.get_lmsr_prices(&prediction_id),
+        (fixed::ONE / 2, fixed::ONE / 2)
+    );
+
+    // Slippage protection uses the same quote the trade is charged
+    let quote = client.quote_buy_shares(&prediction_id, &true, &50_0000000);
+    let result = client.try_buy_shares(
+        &prediction_id,
+        &user1,
+        &true,
+        &50_0000000,
+        &(quote - 1),
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+
+    let paid = client.buy_shares(
+        &prediction_id,
+        &user1,
+        &true,
+        &50_0000000,
+        &quote,
+        &token.address,
+    );
+    assert_eq!(paid, quote);
+    assert_eq!(token.balance(&user1), 1000_0000000 - paid);
+
+    // Buying YES raises the YES price, prices still sum to one
+    let (yes_price, no_price) = client.get_lmsr_prices(&prediction_id);
+    assert!(yes_price > fixed::ONE / 2);
+    assert_eq!(yes_price + no_price, fixed::ONE);
+
+    assert_eq!(client.get_lmsr(&prediction_id).yes_shares, 50_0000000);
+
+    client.buy_shares(
+        &prediction_id,
+        &user2,
+        &false,
+        &30_0000000,
+        &100_0000000,
+        &token.address,
+    );
+
+    // Selling is capped by the shares held and slippage protected
+    let result = client.try_sell_shares(
+        &prediction_id,
+        &user1,
+        &true,
+        &60_0000000,
+        &0,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InsufficientShares)));
+
+    let quote = client.quote_sell_shares(&prediction_id, &true, &10_0000000);
+    let result = client.try_sell_shares(
+        &prediction_id,
+        &user1,
+        &true,
+        &10_0000000,
+        &(quote + 1),
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+
+    let balance = token.balance(&user1);
+    let proceeds = client.sell_shares(
+        &prediction_id,
+        &user1,
+        &true,
+        &10_0000000,
+        &quote,
+        &token.address,
+    );
+    assert_eq!(proceeds, quote);
+    assert_eq!(token.balance(&user1), balance + proceeds);
+    assert_eq!(
+        client
+            .get_lmsr_position(&prediction_id, &user1)
+            .unwrap()
+            .yes_shares,
+        40_0000000
+    );
+
+    // Trading and resolution are bound to the unlock time
+    let result = client.try_resolve_lmsr(&admin, &prediction_id, &true);
+    assert_eq!(result, Err(Ok(Error::ResolveTooEarly)));
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_buy_shares(
+        &prediction_id,
+        &user2,
+        &false,
+        &1_0000000,
+        &100_0000000,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::PredictionExpired)));
+
+    let result = client.try_claim_lmsr(&prediction_id, &user1, &token.address);
+    assert_eq!(result, Err(Ok(Error::NotResolved)));
+
+    let creator_balance = token.balance(&creator);
+    client.resolve_lmsr(&admin, &prediction_id, &true);
+
+    // The creator gets back everything not reserved for the 40 winning shares
+    let prediction = client.get_lmsr(&prediction_id);
+    assert_eq!(prediction.winner, Resolution::Yes);
+    assert_eq!(
+        prediction.creator_return,
+        prediction.subsidy + prediction.collected - 40_0000000
+    );
+    assert_eq!(
+        token.balance(&creator),
+        creator_balance + prediction.creator_return
+    );
+
+    // Each winning share pays one base unit
+    let balance = token.balance(&user1);
+    assert_eq!(
+        client.claim_lmsr(&prediction_id, &user1, &token.address),
+        40_0000000
+    );
+    assert_last_event(
+        &env,
+        &contract_id,
+        &SharesRedeemed {
+            prediction_id,
+            user: user1.clone(),
+            payout: 40_0000000,
+        },
+    );
+    assert_eq!(token.balance(&user1), balance + 40_0000000);
+
+    let result = client.try_claim_lmsr(&prediction_id, &user1, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+    let result = client.try_claim_lmsr(&prediction_id, &user2, &token.address);
+    assert_eq!(result, Err(Ok(Error::LostPrediction)));
+}
+
+#[test]
+fn test_lmsr_price_bounds_and_subsidy_loss() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &100000_0000000);
+    token_admin.mint(&user2, &100000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will ETH reach $5k this year?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_lmsr(
+        &creator,
+        &question,
+        &unlock_time,
+        &100_0000000,
+        &token.address,
+    );
+
+    // Alternate large trades on both sides; prices stay in [0, 1] and sum to 1
+    let trades = [
+        (true, &user1, 500_0000000),
+        (false, &user2, 200_0000000),
+        (true, &user1, 5000_0000000),
+        (false, &user2, 100_0000000),
+        (true, &user1, 20000_0000000),
+    ];
+    let mut last_yes_price = 0;
+    for (outcome, user, shares) in trades {
+        client.buy_shares(
+            &prediction_id,
+            user,
+            &outcome,
+            &shares,
+            &100000_0000000,
+            &token.address,
+        );
+        let (yes_price, no_price) = client.get_lmsr_prices(&prediction_id);
+        assert!((0..=fixed::ONE).contains(&yes_price));
+        assert!((0..=fixed::ONE).contains(&no_price));
+        assert_eq!(yes_price + no_price, fixed::ONE);
+        if outcome {
+            assert!(yes_price >= last_yes_price);
+        }
+        last_yes_price = yes_price;
+    }
+
+    // YES is now all but certain, so a further YES share costs about one unit
+    assert_eq!(last_yes_price, fixed::ONE);
+    let quote = client.quote_buy_shares(&prediction_id, &true, &1_0000000);
+    assert!((1_0000000..=1_0500002).contains(&quote));
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // YES wins after the market moved all the way: the creator loses close
+    // to the whole subsidy but never more than it
+    client.resolve_lmsr(&admin, &prediction_id, &true);
+    let prediction = client.get_lmsr(&prediction_id);
+    let loss = prediction.subsidy - prediction.creator_return;
+    assert!(prediction.creator_return >= 0);
+    assert!(loss <= prediction.subsidy);
+    assert!(loss > prediction.subsidy * 9 / 10);
+
+    client.claim_lmsr(&prediction_id, &user1, &token.address);
+
+    // Every share was paid in full and only the creation fee and trading
+    // fees are left: the treasury's part plus the burned part of the fee
+    let treasury = client.get_treasury(&token.address);
+    assert_eq!(
+        token.balance(&contract_id),
+        treasury + 50_0000000 - 35_0000000
+    );
+}
+
+#[test]
+fn test_lmsr_cancel_expire_and_emergency() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will ETH reach $5k this year?");
+    let unlock_time = 1000000 + 7200;
+    let mut ids = Vec::new(&env);
+    for _ in 0..3 {
+        let prediction_id = client.create_lmsr(
+            &creator,
+            &question,
+            &unlock_time,
+            &100_0000000,
+            &token.address,
+        );
+        ids.push_back(prediction_id);
+    }
+    let (cancelled_id, expired_id, emergency_id) = (
+        ids.get_unchecked(0),
+        ids.get_unchecked(1),
+        ids.get_unchecked(2),
+    );
+
+    for prediction_id in ids.iter() {
+        client.buy_shares(
+            &prediction_id,
+            &user1,
+            &true,
+            &30_0000000,
+            &100_0000000,
+            &token.address,
+        );
+    }
+    client.buy_shares(
+        &cancelled_id,
+        &user1,
+        &true,
+        &20_0000000,
+        &100_0000000,
+        &token.address,
+    );
+    client.buy_shares(
+        &cancelled_id,
+        &user2,
+        &false,
+        &30_0000000,
+        &100_0000000,
+        &token.address,
+    );
+
+    // Each market is indexed once, on the first trade
+    assert_eq!(client.get_user_position_count(&user1), 3);
+
+    // The creator can only back out while nobody else holds shares
+    let result = client.try_cancel_lmsr(&creator, &cancelled_id);
+    assert_eq!(result, Err(Ok(Error::CannotCancel)));
+
+    // A resolver cancels: every share is reserved at the final prices and
+    // the rest goes back to the creator
+    let (yes_price, no_price) = client.get_lmsr_prices(&cancelled_id);
+    let prediction = client.get_lmsr(&cancelled_id);
+    let reserved = (50_0000000 * yes_price + 30_0000000 * no_price + fixed::ONE - 1) / fixed::ONE;
+    let creator_return = prediction.subsidy + prediction.collected - reserved;
+    let balance = token.balance(&creator);
+    client.cancel_lmsr(&admin, &cancelled_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &LmsrCancelled {
+            prediction_id: cancelled_id,
+            caller: admin.clone(),
+            yes_shares: 50_0000000,
+            no_shares: 30_0000000,
+            creator_return,
+        },
+    );
+    assert_eq!(token.balance(&creator), balance + creator_return);
+    assert_eq!(
+        client.get_lmsr(&cancelled_id).status,
+        PredictionStatus::Cancelled
+    );
+    assert_eq!(
+        client.try_buy_shares(
+            &cancelled_id,
+            &user1,
+            &true,
+            &10_0000000,
+            &100_0000000,
+            &token.address,
+        ),
+        Err(Ok(Error::PredictionNotOpen))
+    );
+
+    // Every share redeems at its final price, alone or in a batch
+    let position = client.get_user_positions(&user1, &0, &1).get_unchecked(0);
+    assert_eq!(position.kind, MarketKind::Lmsr);
+    assert_eq!(position.side, 1);
+    assert_eq!(position.amount, 50_0000000);
+    assert_eq!(position.claimable, 50_0000000 * yes_price / fixed::ONE);
+    assert_eq!(
+        client.claim_lmsr(&cancelled_id, &user1, &token.address),
+        50_0000000 * yes_price / fixed::ONE
+    );
+    let batch = client.claim_many(&user2, &vec![&env, cancelled_id]);
+    assert_eq!(
+        batch.results.get_unchecked(0).payout,
+        30_0000000 * no_price / fixed::ONE
+    );
+    assert_eq!(
+        client.try_claim_lmsr(&cancelled_id, &user1, &token.address),
+        Err(Ok(Error::AlreadyClaimed))
+    );
+
+    // A market nobody resolves expires at its deadline
+    assert_eq!(
+        client.try_expire_lmsr(&expired_id),
+        Err(Ok(Error::DeadlineNotReached))
+    );
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + DEFAULT_RESOLUTION_WINDOW,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    assert_eq!(
+        client.try_resolve_lmsr(&admin, &expired_id, &true),
+        Err(Ok(Error::ResolutionExpired))
+    );
+    let (yes_price, _) = client.get_lmsr_prices(&expired_id);
+    let prediction = client.get_lmsr(&expired_id);
+    let reserved = (30_0000000 * yes_price + fixed::ONE - 1) / fixed::ONE;
+    client.expire_lmsr(&expired_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &LmsrExpired {
+            prediction_id: expired_id,
+            yes_shares: 30_0000000,
+            no_shares: 0,
+            creator_return: prediction.subsidy + prediction.collected - reserved,
+        },
+    );
+    assert_eq!(
+        client.get_lmsr(&expired_id).status,
+        PredictionStatus::Expired
+    );
+    assert_eq!(
+        client.claim_lmsr(&expired_id, &user1, &token.address),
+        30_0000000 * yes_price / fixed::ONE
+    );
+
+    // Once resolution has been paused long enough, traders sell their
+    // shares back to the market even though trading has closed
+    client.pause(&admin, &Operation::Resolve);
+    let result = client.try_emergency_withdraw_lmsr(&emergency_id, &user1, &token.address);
+    assert_eq!(result, Err(Ok(Error::EmergencyNotAvailable)));
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + DEFAULT_RESOLUTION_WINDOW + EMERGENCY_DELAY,
+        protocol_version: 23,
+        sequence_number: 30,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let quote = client.quote_sell_shares(&emergency_id, &true, &30_0000000);
+    let balance = token.balance(&user1);
+    assert_eq!(
+        client.emergency_withdraw_lmsr(&emergency_id, &user1, &token.address),
+        quote
+    );
+    assert_last_event(
+        &env,
+        &contract_id,
+        &EmergencyWithdrawn {
+            prediction_id: emergency_id,
+            user: user1.clone(),
+            amount: quote,
+        },
+    );
+    assert_eq!(token.balance(&user1), balance + quote);
+    let result = client.try_emergency_withdraw_lmsr(&emergency_id, &user1, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+
+    // With no shares left the creator can cancel and take back the subsidy
+    let prediction = client.get_lmsr(&emergency_id);
+    assert_eq!(prediction.yes_shares, 0);
+    let balance = token.balance(&creator);
+    client.cancel_lmsr(&creator, &emergency_id);
+    assert_eq!(
+        token.balance(&creator),
+        balance + prediction.subsidy + prediction.collected
+    );
+    assert!(prediction.collected >= 0);
+}
+
+#[test]
+fn test_lmsr_no_buying_after_emergency_withdrawal() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Trading stays open well past the point the emergency exit opens
+    let prediction_id = client.create_lmsr(
+        &creator,
+        &String::from_str(&env, "Will BTC reach $100k this year?"),
+        &(1000000 + 2 * EMERGENCY_DELAY),
+        &100_0000000,
+        &token.address,
+    );
+    client.buy_shares(
+        &prediction_id,
+        &user,
+        &true,
+        &30_0000000,
+        &100_0000000,
+        &token.address,
+    );
+
+    // The creator is indexed on creation, and only then
+    client.buy_shares(
+        &prediction_id,
+        &creator,
+        &false,
+        &10_0000000,
+        &100_0000000,
+        &token.address,
+    );
+    assert_eq!(client.get_user_position_count(&creator), 1);
+    assert_eq!(client.get_user_position_count(&user), 1);
+
+    client.pause(&admin, &Operation::Resolve);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000 + EMERGENCY_DELAY,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.emergency_withdraw_lmsr(&prediction_id, &user, &token.address);
+
+    // Shares bought into the closed position could never be claimed
+    let balance = token.balance(&user);
+    let result = client.try_buy_shares(
+        &prediction_id,
+        &user,
+        &true,
+        &10_0000000,
+        &100_0000000,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+    assert_eq!(token.balance(&user), balance);
+    assert_eq!(client.get_lmsr(&prediction_id).yes_shares, 0);
+}
+
+#[test]
+fn test_cpmm_prediction() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will ETH reach $5k this year?");
+    let unlock_time = 1000000 + 7200;
+
+    let result = client.try_create_cpmm(
+        &creator,
+        &question,
+        &unlock_time,
+        &100_0000000,
+        &1001,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InvalidFee)));
+
+    // 2% of each trade goes to the liquidity providers
+    let prediction_id = client.create_cpmm(
+        &creator,
+        &question,
+        &unlock_time,
+        &100_0000000,
+        &200,
+        &token.address,
+    );
+    assert_eq!(
+        token.balance(&creator),
+        1000_0000000 - 50_0000000 - 100_0000000
+    );
+    assert_eq!(
+        client.get_cpmm_prices(&prediction_id),
+        (fixed::ONE / 2, fixed::ONE / 2)
+    );
+
+    // 10 in, 0.2 fee: 9.8 sets join the pool and the YES reserve falls to
+    // keep 100 * 100 constant, ceil(100 * 100 / 109.8) = 91.0746813
+    let quote = client.quote_buy_outcome(&prediction_id, &true, &10_0000000);
+    assert_eq!(quote, 109_8000000 - 91_0746813);
+
+    let result = client.try_buy_outcome(
+        &prediction_id,
+        &user1,
+        &true,
+        &10_0000000,
+        &(quote + 1),
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+
+    let tokens = client.buy_outcome(
+        &prediction_id,
+        &user1,
+        &true,
+        &10_0000000,
+        &quote,
+        &token.address,
+    );
+    assert_eq!(tokens, quote);
+    assert_eq!(
+        client
+            .get_cpmm_position(&prediction_id, &user1)
+            .unwrap()
+            .yes_tokens,
+        tokens
+    );
+
+    let prediction = client.get_cpmm(&prediction_id);
+    assert_eq!(prediction.yes_reserve, 91_0746813);
+    assert_eq!(prediction.no_reserve, 109_8000000);
+    assert_eq!(prediction.sets, 109_8000000);
+    let (yes_price, no_price) = client.get_cpmm_prices(&prediction_id);
+    assert!(yes_price > fixed::ONE / 2);
+    assert_eq!(yes_price + no_price, fixed::ONE);
+
+    client.buy_outcome(
+        &prediction_id,
+        &user2,
+        &false,
+        &5_0000000,
+        &0,
+        &token.address,
+    );
+
+    // Selling is capped by the tokens held and slippage protected
+    let result = client.try_sell_outcome(
+        &prediction_id,
+        &user1,
+        &true,
+        &(tokens + 1),
+        &0,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::InsufficientShares)));
+
+    let quote = client.quote_sell_outcome(&prediction_id, &true, &5_0000000);
+    let result = client.try_sell_outcome(
+        &prediction_id,
+        &user1,
+        &true,
+        &5_0000000,
+        &(quote + 1),
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::SlippageExceeded)));
+
+    let balance = token.balance(&user1);
+    let proceeds = client.sell_outcome(
+        &prediction_id,
+        &user1,
+        &true,
+        &5_0000000,
+        &quote,
+        &token.address,
+    );
+    assert_eq!(proceeds, quote);
+    assert_eq!(token.balance(&user1), balance + proceeds);
+
+    // YES is above even, so selling 5 YES returns less than 5
+    assert!(proceeds > 0 && proceeds < 5_0000000);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let result = client.try_buy_outcome(
+        &prediction_id,
+        &user2,
+        &false,
+        &1_0000000,
+        &0,
+        &token.address,
+    );
+    assert_eq!(result, Err(Ok(Error::PredictionExpired)));
+
+    let result = client.try_claim_cpmm(&prediction_id, &user1, &token.address);
+    assert_eq!(result, Err(Ok(Error::NotResolved)));
+    let result = client.try_withdraw_liquidity(&prediction_id, &creator, &token.address);
+    assert_eq!(result, Err(Ok(Error::NotResolved)));
+
+    client.resolve_cpmm(&admin, &prediction_id, &true);
+    assert_eq!(client.get_cpmm_prices(&prediction_id), (fixed::ONE, 0));
+
+    // Winning tokens redeem 1:1
+    let payout = client.claim_cpmm(&prediction_id, &user1, &token.address);
+    assert_eq!(payout, tokens - 5_0000000);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &SharesRedeemed {
+            prediction_id,
+            user: user1.clone(),
+            payout,
+        },
+    );
+
+    let result = client.try_claim_cpmm(&prediction_id, &user1, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+    let result = client.try_claim_cpmm(&prediction_id, &user2, &token.address);
+    assert_eq!(result, Err(Ok(Error::LostPrediction)));
+
+    // The creator takes the whole winning reserve and every fee
+    let prediction = client.get_cpmm(&prediction_id);
+    let withdrawn = client.withdraw_liquidity(&prediction_id, &creator, &token.address);
+    assert_eq!(withdrawn, prediction.yes_reserve + prediction.fees);
+
+    let result = client.try_withdraw_liquidity(&prediction_id, &creator, &token.address);
+    assert_eq!(result, Err(Ok(Error::AlreadyClaimed)));
+
+    // Everything is paid out except the burned part of the creation fee
+    let treasury = client.get_treasury(&token.address);
+    assert_eq!(
+        token.balance(&contract_id),
+        treasury + 50_0000000 - 35_0000000
+    );
+}
+
+#[test]
+fn test_cpmm_liquidity_fees() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let provider = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&provider, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will ETH reach $5k this year?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_cpmm(
+        &creator,
+        &question,
+        &unlock_time,
+        &100_0000000,
+        &100,
+        &token.address,
+    );
+
+    // 1% of 100 in fees before the provider joins
+    client.buy_outcome(
+        &prediction_id,
+        &user1,
+        &true,
+        &100_0000000,
+        &0,
+        &token.address,
+    );
+    assert_eq!(client.get_cpmm(&prediction_id).fees, 1_0000000);
+
+    let result = client.try_add_liquidity(&prediction_id, &provider, &1, &token.address);
+    assert_eq!(result, Err(Ok(Error::InvalidLiquidity)));
+
+    // The pool is skewed towards YES, so the provider's surplus YES tokens
+    // come back to them and the price only moves by rounding
+    let (yes_price, _) = client.get_cpmm_prices(&prediction_id);
+    let shares = client.add_liquidity(&prediction_id, &provider, &100_0000000, &token.address);
+    let moved = client.get_cpmm_prices(&prediction_id).0 - yes_price;
+    assert!(moved.abs() < fixed::ONE / 1_000_000_000);
+
+    let prediction = client.get_cpmm(&prediction_id);
+    let returned = client.get_cpmm_position(&prediction_id, &provider).unwrap();
+    assert!(returned.yes_tokens > 0);
+    assert_eq!(returned.no_tokens, 0);
+    assert_eq!(
+        client
+            .get_lp_position(&prediction_id, &provider)
+            .unwrap()
+            .shares,
+        shares
+    );
+    assert_eq!(prediction.lp_supply, 100_0000000 + shares);
+
+    // Providers are indexed once, however they hold a stake in the market
+    assert_eq!(client.get_user_position_count(&creator), 1);
+    assert_eq!(client.get_user_position_count(&provider), 1);
+
+    // 1% of 50 in fees after, shared by both providers
+    client.buy_outcome(
+        &prediction_id,
+        &user2,
+        &false,
+        &50_0000000,
+        &0,
+        &token.address,
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.resolve_cpmm(&admin, &prediction_id, &false);
+
+    // The provider only shares in the fees paid after they joined
+    let prediction = client.get_cpmm(&prediction_id);
+    let position = client.get_lp_position(&prediction_id, &provider).unwrap();
+    let amount = prediction.no_reserve * shares / prediction.lp_supply;
+    let fees = prediction.fees * shares / prediction.lp_supply - position.fee_debt;
+    assert!(fees > 0 && fees < 5000000);
+
+    let withdrawn = client.withdraw_liquidity(&prediction_id, &provider, &token.address);
+    assert_eq!(withdrawn, amount + fees);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &LiquidityWithdrawn {
+            prediction_id,
+            provider: provider.clone(),
+            lp_shares: shares,
+            amount,
+            fees,
+        },
+    );
+
+    // The creator keeps every fee from before the provider joined
+    let withdrawn = client.withdraw_liquidity(&prediction_id, &creator, &token.address);
+    let prediction = client.get_cpmm(&prediction_id);
+    assert_eq!(prediction.lp_supply, 0);
+    assert_eq!(prediction.no_reserve, 0);
+    assert_eq!(prediction.fees, 0);
+    assert!(withdrawn > 1_0000000);
+
+    client.claim_cpmm(&prediction_id, &user2, &token.address);
+    let result = client.try_claim_cpmm(&prediction_id, &provider, &token.address);
+    assert_eq!(result, Err(Ok(Error::LostPrediction)));
+
+    // Every fee and winning token was paid out exactly
+    let treasury = client.get_treasury(&token.address);
+    assert_eq!(
+        token.balance(&contract_id),
+        treasury + 50_0000000 - 35_0000000
+    );
+}
+
+#[test]
+fn test_cpmm_cancel_expire_and_emergency() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will ETH reach $5k this year?");
+    let unlock_time = 1000000 + 7200;
+    let mut ids = Vec::new(&env);
+    for outcome in [true, false, true] {
+        let prediction_id = client.create_cpmm(
+            &creator,
+            &question,
+            &unlock_time,
+            &100_0000000,
+            &200,
+            &token.address,
+        );
+        client.buy_outcome(
+            &prediction_id,
+            &user1,
+            &outcome,
+            &10_0000000,
+            &0,
+            &token.address,
+        );
+        ids.push_back(prediction_id);
+    }
+    let (cancelled_id, expired_id, emergency_id) = (
+        ids.get_unchecked(0),
+        ids.get_unchecked(1),
+        ids.get_unchecked(2),
+    );
+    assert_eq!(client.get_user_position_count(&user1), 3);
+    assert_eq!(client.get_user_position_count(&creator), 3);
+
+    // The creator can only back out while nobody else is in the market
+    let result = client.try_cancel_cpmm(&creator, &cancelled_id);
+    assert_eq!(result, Err(Ok(Error::CannotCancel)));
+    let result = client.try_cancel_cpmm(&user1, &cancelled_id);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+
+    // A resolver cancels and the market settles at its final prices
+    let (yes_price, no_price) = client.get_cpmm_prices(&cancelled_id);
+    let prediction = client.get_cpmm(&cancelled_id);
+    client.cancel_cpmm(&admin, &cancelled_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &CpmmCancelled {
+            prediction_id: cancelled_id,
+            caller: admin.clone(),
+            yes_reserve: prediction.yes_reserve,
+            no_reserve: prediction.no_reserve,
+            yes_price,
+        },
+    );
+    assert_eq!(client.get_cpmm_prices(&cancelled_id), (yes_price, no_price));
+    assert_eq!(
+        client.try_buy_outcome(
+            &cancelled_id,
+            &user1,
+            &true,
+            &10_0000000,
+            &0,
+            &token.address,
+        ),
+        Err(Ok(Error::PredictionNotOpen))
+    );
+
+    // Each token redeems at its final price and the provider withdraws the
+    // reserves at the same prices, which together pay out every set
+    let tokens = client
+        .get_cpmm_position(&cancelled_id, &user1)
+        .unwrap()
+        .yes_tokens;
+    let position = client.get_user_positions(&user1, &0, &1).get_unchecked(0);
+    assert_eq!(position.kind, MarketKind::Cpmm);
+    assert_eq!(position.amount, tokens);
+    assert_eq!(position.claimable, tokens * yes_price / fixed::ONE);
+    let redeemed = client.claim_cpmm(&cancelled_id, &user1, &token.address);
+    assert_eq!(redeemed, tokens * yes_price / fixed::ONE);
+
+    let reserves =
+        (prediction.yes_reserve * yes_price + prediction.no_reserve * no_price) / fixed::ONE;
+    let withdrawn = client.withdraw_liquidity(&cancelled_id, &creator, &token.address);
+    assert_eq!(withdrawn, reserves + prediction.fees);
+    assert!(redeemed + withdrawn <= 110_0000000);
+    assert!(redeemed + withdrawn >= 110_0000000 - 2);
+
+    // Once resolution has been paused long enough, anyone can cancel
+    client.pause(&admin, &Operation::Resolve);
+    let result = client.try_cancel_cpmm(&user2, &emergency_id);
+    assert_eq!(result, Err(Ok(Error::Unauthorized)));
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000 + EMERGENCY_DELAY,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    client.cancel_cpmm(&user2, &emergency_id);
+    assert_eq!(
+        client.get_cpmm(&emergency_id).status,
+        PredictionStatus::Cancelled
+    );
+    assert!(client.claim_cpmm(&emergency_id, &user1, &token.address) > 0);
+    assert!(client.withdraw_liquidity(&emergency_id, &creator, &token.address) > 0);
+
+    // The pause pushed the other market's deadline back
+    assert_eq!(
+        client.try_expire_cpmm(&expired_id),
+        Err(Ok(Error::DeadlineNotReached))
+    );
+    client.unpause(&admin, &Operation::Resolve);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + DEFAULT_RESOLUTION_WINDOW + EMERGENCY_DELAY,
+        protocol_version: 23,
+        sequence_number: 30,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+    assert_eq!(
+        client.try_resolve_cpmm(&admin, &expired_id, &true),
+        Err(Ok(Error::ResolutionExpired))
+    );
+    let (_, no_price) = client.get_cpmm_prices(&expired_id);
+    let prediction = client.get_cpmm(&expired_id);
+    client.expire_cpmm(&expired_id);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &CpmmExpired {
+            prediction_id: expired_id,
+            yes_reserve: prediction.yes_reserve,
+            no_reserve: prediction.no_reserve,
+            yes_price: fixed::ONE - no_price,
+        },
+    );
+
+    let tokens = client
+        .get_cpmm_position(&expired_id, &user1)
+        .unwrap()
+        .no_tokens;
+    let batch = client.claim_many(&user1, &vec![&env, expired_id]);
+    assert_eq!(
+        batch.results.get_unchecked(0).payout,
+        tokens * no_price / fixed::ONE
+    );
+    assert_eq!(
+        client.try_claim_cpmm(&expired_id, &user1, &token.address),
+        Err(Ok(Error::AlreadyClaimed))
+    );
+}
+
+#[test]
+fn test_cpmm_emergency_cancel_needs_closed_trading() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let attacker = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&attacker, &10000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Trading stays open well past the point the emergency exit opens
+    let unlock_time = 1000000 + 2 * EMERGENCY_DELAY;
+    let prediction_id = client.create_cpmm(
+        &creator,
+        &String::from_str(&env, "Will ETH reach $5k this year?"),
+        &unlock_time,
+        &100_0000000,
+        &200,
+        &token.address,
+    );
+    client.pause(&admin, &Operation::Resolve);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000 + EMERGENCY_DELAY,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    // Buying to move the price and cancelling at it straight away would
+    // redeem the tokens for more than they cost, at the providers' expense
+    client.buy_outcome(
+        &prediction_id,
+        &attacker,
+        &true,
+        &500_0000000,
+        &0,
+        &token.address,
+    );
+    let result = client.try_cancel_cpmm(&attacker, &prediction_id);
+    assert_eq!(result, Err(Ok(Error::EmergencyNotAvailable)));
+    assert_eq!(
+        client.get_cpmm(&prediction_id).status,
+        PredictionStatus::Open
+    );
+
+    // A trader who also provides liquidity is only indexed once
+    client.add_liquidity(&prediction_id, &attacker, &100_0000000, &token.address);
+    assert_eq!(client.get_user_position_count(&attacker), 1);
+    assert_eq!(client.get_user_position_count(&creator), 1);
+
+    // Once trading is paused the price is fixed and anyone can cancel
+    client.pause(&admin, &Operation::Stake);
+    client.cancel_cpmm(&attacker, &prediction_id);
+    assert_eq!(
+        client.get_cpmm(&prediction_id).status,
+        PredictionStatus::Cancelled
+    );
+}
+
+#[test]
+fn test_transfer_position() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let user1 = Address::generate(&env);
+    let user2 = Address::generate(&env);
+    let buyer = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&user1, &1000_0000000);
+    token_admin.mint(&user2, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let question = String::from_str(&env, "Will Bitcoin hit $100k?");
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &question,
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &user1, &true, &300_0000000, &token.address);
+    client.stake(&prediction_id, &user2, &false, &400_0000000, &token.address);
+
+    // Part of a stake moves to an address with no stake of its own
+    client.transfer_position(&prediction_id, &user1, &buyer, &100_0000000);
+    assert_last_event(
+        &env,
+        &contract_id,
+        &PositionTransferred {
+            prediction_id,
+            from: user1.clone(),
+            to: buyer.clone(),
+            choice: true,
+            amount: 100_0000000,
+            remaining: 200_0000000,
+            total: 100_0000000,
+        },
+    );
+    assert_eq!(
+        client.get_stake(&prediction_id, &buyer),
+        Some(Stake {
+            user: buyer.clone(),
+            choice: true,
+            amount: 100_0000000,
+            claimed: false,
+        })
+    );
+    assert_eq!(
+        client.get_stake(&prediction_id, &user1).unwrap().amount,
+        200_0000000
+    );
+    assert_eq!(client.get_user_position_count(&buyer), 1);
+
+    // The pools are untouched
+    let prediction = client.get_prediction(&prediction_id);
+    assert_eq!(prediction.yes_pool, 400_0000000);
+    assert_eq!(prediction.no_pool, 400_0000000);
+
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &user1, &user1, &1),
+        Err(Ok(Error::InvalidTransfer))
+    );
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &user1, &buyer, &0),
+        Err(Ok(Error::StakeTooLow))
+    );
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &user1, &buyer, &200_0000001),
+        Err(Ok(Error::InvalidTransfer))
+    );
+    // Nor can it leave less than the minimum stake behind
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &user1, &buyer, &150_0000000),
+        Err(Ok(Error::InvalidTransfer))
+    );
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &user1, &user2, &100_0000000),
+        Err(Ok(Error::CannotSwitchSides))
+    );
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &admin, &buyer, &1),
+        Err(Ok(Error::StakeNotFound))
+    );
+
+    // The rest merges into the creator's stake on the same side
+    client.transfer_position(&prediction_id, &user1, &creator, &200_0000000);
+    assert_eq!(client.get_stake(&prediction_id, &user1), None);
+    assert_eq!(
+        client.get_stake(&prediction_id, &creator).unwrap().amount,
+        300_0000000
+    );
+    assert_eq!(
+        client.get_stake_history(&prediction_id, &user1),
+        vec![
+            &env,
+            StakeIncrement {
+                amount: 300_0000000,
+                timestamp: 1000000,
+            },
+            StakeIncrement {
+                amount: -100_0000000,
+                timestamp: 1000000,
+            },
+            StakeIncrement {
+                amount: -200_0000000,
+                timestamp: 1000000,
+            },
+        ]
+    );
+
+    env.ledger().set(LedgerInfo {
+        timestamp: unlock_time + 100,
+        protocol_version: 23,
+        sequence_number: 20,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    client.lock(&prediction_id);
+    client.resolve(&admin, &prediction_id, &true);
+
+    // Claim rights go with the stake: 760 prize pool over 400 YES
+    assert_eq!(
+        client.try_claim(&prediction_id, &user1, &token.address),
+        Err(Ok(Error::StakeNotFound))
+    );
+    assert_eq!(
+        client.claim(&prediction_id, &creator, &token.address),
+        570_0000000
+    );
+
+    // Stakes stop changing hands once the prediction is settled
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &buyer, &user1, &100_0000000),
+        Err(Ok(Error::AlreadyResolved))
+    );
+    assert_eq!(
+        client.claim(&prediction_id, &buyer, &token.address),
+        190_0000000
+    );
+}
+
+#[test]
+fn test_transfer_position_griefing() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let creator = Address::generate(&env);
+    let victim = Address::generate(&env);
+    let attacker = Address::generate(&env);
+
+    let (token, token_admin) = create_token_contract(&env, &admin);
+    token_admin.mint(&creator, &1000_0000000);
+    token_admin.mint(&victim, &1000_0000000);
+    token_admin.mint(&attacker, &1000_0000000);
+
+    let contract_id = env.register(TimeLockContract, ());
+    let client = TimeLockContractClient::new(&env, &contract_id);
+    client.initialize(&admin);
+
+    env.ledger().set(LedgerInfo {
+        timestamp: 1000000,
+        protocol_version: 23,
+        sequence_number: 10,
+        network_id: Default::default(),
+        base_reserve: 10,
+        min_temp_entry_ttl: 10,
+        min_persistent_entry_ttl: 10,
+        max_entry_ttl: 3110400,
+    });
+
+    let unlock_time = 1000000 + 7200;
+    let prediction_id = client.create_prediction(
+        &creator,
+        &String::from_str(&env, "Will Bitcoin hit $100k?"),
+        &unlock_time,
+        &unlock_time,
+        &true,
+        &token.address,
+    );
+    client.stake(&prediction_id, &victim, &true, &100_0000000, &token.address);
+    client.stake(
+        &prediction_id,
+        &attacker,
+        &true,
+        &500_0000000,
+        &token.address,
+    );
+
+    // Dust transfers that would each take up an entry of the victim's
+    // history are rejected
+    assert_eq!(
+        client.try_transfer_position(&prediction_id, &attacker, &victim, &1),
+        Err(Ok(Error::StakeTooLow))
+    );
+
+    // Without the victim's signature nothing can be pushed onto them at all
+    env.mock_auths(&[MockAuth {
+        address: &attacker,
+        invoke: &MockAuthInvoke {
+            contract: &contract_id,
+            fn_name: "transfer_position",
+            args: (prediction_id, &attacker, &victim, 100_0000000i128).into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    assert!(client
+        .try_transfer_position(&prediction_id, &attacker, &victim, &100_0000000)
+        .is_err());
+    assert_eq!(client.get_stake_history(&prediction_id, &victim).len(), 1);
+
+    // With it the transfer goes through, and both parties signed
+    env.mock_all_auths();
+    client.transfer_position(&prediction_id, &attacker, &victim, &100_0000000);
+    let auths = env.auths();
+    assert!(auths.iter().any(|(signer, _)| *signer == attacker));
+    assert!(auths.iter().any(|(signer, _)| *signer == victim));
+    assert_eq!(
+        client.get_stake(&prediction_id, &victim).unwrap().amount,
+        200_0000000
+    );
 }
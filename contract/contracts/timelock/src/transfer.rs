@@ -0,0 +1,105 @@
+//! Transferring binary stakes between addresses.
+//!
+//! A stake can be moved in whole or in part to another address, e.g. after
+//! selling or gifting it off-platform, until its prediction is settled. The
+//! receiver inherits the claim, refund and withdrawal rights of what they
+//! receive. If they already hold a stake in the prediction it must be on the
+//! same side, and the two merge. The pools do not change, so neither do
+//! prices or payouts.
+//!
+//! Both stake histories record the transfer: a negative entry for the sender
+//! and a positive one for the receiver. Since every entry counts towards
+//! `MAX_STAKE_INCREMENTS`, the receiver must also sign, and both the amount
+//! moved and any amount left behind must be at least the minimum stake, so
+//! nobody can fill another user's history with dust.
+
+use soroban_sdk::{contractimpl, Address, Env};
+
+use crate::{
+    record_increment, require_not_paused, require_unsettled, storage, Error, MarketKind, Operation,
+    PositionTransferred, Stake, TimeLockContract, TimeLockContractArgs, TimeLockContractClient,
+};
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Transfer `amount` of `from`'s stake in an unsettled binary prediction
+    /// to `to`. Both must sign.
+    pub fn transfer_position(
+        env: Env,
+        prediction_id: u64,
+        from: Address,
+        to: Address,
+        amount: i128,
+    ) -> Result<(), Error> {
+        from.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        if from == to {
+            return Err(Error::InvalidTransfer);
+        }
+        to.require_auth();
+
+        let prediction =
+            storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        require_unsettled(&prediction.status)?;
+
+        let mut sender =
+            storage::get_stake(&env, prediction_id, &from).ok_or(Error::StakeNotFound)?;
+        if sender.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let min_stake = storage::get_config(&env).min_stake;
+        if amount < min_stake {
+            return Err(Error::StakeTooLow);
+        }
+        let remaining = sender.amount - amount;
+        if remaining < 0 || (remaining > 0 && remaining < min_stake) {
+            return Err(Error::InvalidTransfer);
+        }
+
+        let mut receiver = match storage::get_stake(&env, prediction_id, &to) {
+            Some(stake) if stake.choice != sender.choice => return Err(Error::CannotSwitchSides),
+            Some(stake) if stake.claimed => return Err(Error::AlreadyClaimed),
+            Some(stake) => stake,
+            None => {
+                if storage::get_stake_history(&env, prediction_id, &to).is_empty() {
+                    storage::add_user_position(&env, &to, prediction_id, MarketKind::Binary);
+                }
+                Stake {
+                    user: to.clone(),
+                    choice: sender.choice,
+                    amount: 0,
+                    claimed: false,
+                }
+            }
+        };
+        record_increment(&env, prediction_id, &from, -amount)?;
+        record_increment(&env, prediction_id, &to, amount)?;
+
+        // An emptied stake is removed, as after a withdrawal
+        sender.amount -= amount;
+        if sender.amount == 0 {
+            storage::remove_stake(&env, prediction_id, &from);
+        } else {
+            storage::set_stake(&env, prediction_id, &sender);
+        }
+
+        receiver.amount += amount;
+        storage::set_stake(&env, prediction_id, &receiver);
+
+        PositionTransferred {
+            prediction_id,
+            from,
+            to,
+            choice: sender.choice,
+            amount,
+            remaining: sender.amount,
+            total: receiver.amount,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+//! Contract upgrades and storage migrations.
+//!
+//! `upgrade` swaps the contract code in place, so the contract id and every
+//! market survive a new release. The layout of stored data is tracked by a
+//! schema version:
+//!
+//! - version 1: the original contract. Everything lived in instance storage,
+//!   predictions did not record their token or settlement, `winner` was an
+//!   `Option<bool>` and the treasury was a single untyped balance.
+//! - version 2: predictions in persistent storage with a single
+//!   `unlock_time` for both the end of staking and the earliest resolution.
+//! - version 3: predictions have `close_time` and `resolve_after` but no
+//!   resolution deadline, and the config has no resolution window.
+//! - version 4: predictions have no `forfeited` pool and the config has no
+//!   exit penalty.
+//! - version 5: predictions have no `pause_offset`, categorical, scalar,
+//!   LMSR and CPMM predictions have no resolution deadline, and CPMM
+//!   predictions have no `void_price`.
+//! - version 6: the current layout.
+//!
+//! After upgrading the code, the admin calls `migrate` once to bring the
+//! contract-wide data to the current version, then `migrate_prediction` for
+//! each market still in instance storage. Stakes kept their layout and are
+//! only moved. Predictions of every type from versions 2 to 5 are converted
+//! when they are read and rewritten on their next update. Until then, their
+//! resolution deadline follows the current config's resolution window, and
+//! it is extended by any resolution pause since the upgrade.
+
+use soroban_sdk::{
+    contractimpl, contracttype, vec, Address, BytesN, Env, IntoVal, Map, String, Symbol,
+    TryFromVal, Val, Vec,
+};
+
+use crate::{
+    add_to_treasury, require_admin, storage, Config, ContractUpgraded, CpmmPrediction, DataKey,
+    Error, MarketKind, Migrated, Prediction, PredictionStatus, Resolution, Settlement, Stake,
+    TimeLockContract, TimeLockContractArgs, TimeLockContractClient, DEFAULT_EXIT_PENALTY_BPS,
+    DEFAULT_PENALTY_TREASURY_SHARE_BPS, DEFAULT_RESOLUTION_WINDOW,
+};
+
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// A version 1 prediction.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct LegacyPrediction {
+    pub id: u64,
+    pub creator: Address,
+    pub question: String,
+    pub unlock_time: u64,
+    pub yes_pool: i128,
+    pub no_pool: i128,
+    pub status: PredictionStatus,
+    pub winner: Option<bool>,
+}
+
+impl LegacyPrediction {
+    /// Convert to the current layout. Version 1 took the platform fee on
+    /// every claim, so the fee of a resolved market is recorded but not
+    /// credited to the treasury again.
+    fn into_current(self, env: &Env, token: Address) -> Prediction {
+        let config = storage::get_config(env);
+        let total_pool = self.yes_pool + self.no_pool;
+        let (winner, winning_pool) = match self.winner {
+            None => (Resolution::Pending, 0),
+            Some(true) => (Resolution::Yes, self.yes_pool),
+            Some(false) => (Resolution::No, self.no_pool),
+        };
+
+        let (winner, settlement) = if winner == Resolution::Pending {
+            (winner, Settlement::default())
+        } else if winning_pool == 0 {
+            // Nobody could claim from a market won by an empty side
+            (Resolution::Invalid, Settlement::refund(total_pool))
+        } else {
+            let (prize_pool, platform_fee) = config.split_pool(total_pool);
+            let settlement = Settlement {
+                platform_fee,
+                prize_pool,
+                eligible_stake: winning_pool,
+                ..Default::default()
+            };
+            (winner, settlement)
+        };
+
+        Prediction {
+            id: self.id,
+            creator: self.creator,
+            question: self.question,
+            token,
+            close_time: self.unlock_time,
+            resolve_after: self.unlock_time,
+            resolve_deadline: self.unlock_time + config.resolution_window,
+            pause_offset: 0,
+            yes_pool: self.yes_pool,
+            no_pool: self.no_pool,
+            forfeited: 0,
+            status: self.status,
+            winner,
+            settlement,
+        }
+    }
+}
+
+/// Whether a stored prediction uses the version 1 layout, which had no token.
+/// Decoding a struct with the wrong layout aborts, so check its keys first.
+pub(crate) fn is_legacy_prediction(env: &Env, raw: &Map<Symbol, Val>) -> bool {
+    !raw.contains_key(Symbol::new(env, "token"))
+}
+
+/// Decode a stored prediction written by version 2 or later, filling in the
+/// fields added since. Returns None for version 1, which needs
+/// `migrate_prediction`.
+pub(crate) fn decode_prediction(env: &Env, mut raw: Map<Symbol, Val>) -> Option<Prediction> {
+    if is_legacy_prediction(env, &raw) {
+        return None;
+    }
+
+    // Version 2 closed staking and opened resolution at a single unlock time
+    let unlock_time = Symbol::new(env, "unlock_time");
+    if let Some(time) = raw.get(unlock_time.clone()) {
+        raw.remove(unlock_time);
+        raw.set(Symbol::new(env, "close_time"), time);
+        raw.set(Symbol::new(env, "resolve_after"), time);
+    }
+
+    // Versions 2 and 3 had no resolution deadline
+    let resolve_deadline = Symbol::new(env, "resolve_deadline");
+    if !raw.contains_key(resolve_deadline.clone()) {
+        let resolve_after = raw.get(Symbol::new(env, "resolve_after"))?;
+        let resolve_after = u64::try_from_val(env, &resolve_after).ok()?;
+        let deadline = resolve_after + storage::get_config(env).resolution_window;
+        raw.set(resolve_deadline, deadline.into_val(env));
+    }
+
+    decode_with_defaults(
+        env,
+        raw,
+        [
+            ("forfeited", 0i128.into_val(env)),   // Version 5
+            ("pause_offset", 0u64.into_val(env)), // Version 6
+        ],
+    )
+}
+
+/// Decode a stored categorical, scalar or LMSR prediction, filling in the
+/// resolution deadline and pause offset added in version 6.
+pub(crate) fn decode_unlocked_market<T: TryFromVal<Env, Val>>(
+    env: &Env,
+    raw: Map<Symbol, Val>,
+) -> Option<T> {
+    let deadline = default_deadline(env, &raw)?;
+    decode_with_defaults(
+        env,
+        raw,
+        [
+            ("resolve_deadline", deadline.into_val(env)),
+            ("pause_offset", 0u64.into_val(env)),
+        ],
+    )
+}
+
+/// Decode a stored CPMM prediction, filling in the resolution deadline,
+/// pause offset and void price added in version 6.
+pub(crate) fn decode_cpmm(env: &Env, raw: Map<Symbol, Val>) -> Option<CpmmPrediction> {
+    let deadline = default_deadline(env, &raw)?;
+    decode_with_defaults(
+        env,
+        raw,
+        [
+            ("resolve_deadline", deadline.into_val(env)),
+            ("pause_offset", 0u64.into_val(env)),
+            ("void_price", 0i128.into_val(env)),
+        ],
+    )
+}
+
+/// The resolution deadline of a market stored without one: its unlock time
+/// plus the current resolution window.
+fn default_deadline(env: &Env, raw: &Map<Symbol, Val>) -> Option<u64> {
+    let unlock_time = raw.get(Symbol::new(env, "unlock_time"))?;
+    let unlock_time = u64::try_from_val(env, &unlock_time).ok()?;
+    Some(unlock_time + storage::get_config(env).resolution_window)
+}
+
+/// Decode the stored config, filling in the parameters added since version 3
+/// with their defaults.
+pub(crate) fn decode_config(env: &Env, raw: Map<Symbol, Val>) -> Option<Config> {
+    decode_with_defaults(
+        env,
+        raw,
+        [
+            ("resolution_window", DEFAULT_RESOLUTION_WINDOW.into_val(env)), // Version 4
+            ("exit_penalty_bps", DEFAULT_EXIT_PENALTY_BPS.into_val(env)),   // Version 5
+            (
+                "penalty_treasury_share_bps",
+                DEFAULT_PENALTY_TREASURY_SHARE_BPS.into_val(env),
+            ),
+        ],
+    )
+}
+
+/// Decode a stored struct, first setting each field in `added` it lacks to
+/// the given default.
+fn decode_with_defaults<T: TryFromVal<Env, Val>, const N: usize>(
+    env: &Env,
+    mut raw: Map<Symbol, Val>,
+    added: [(&str, Val); N],
+) -> Option<T> {
+    for (field, default) in added {
+        let key = Symbol::new(env, field);
+        if !raw.contains_key(key.clone()) {
+            raw.set(key, default);
+        }
+    }
+    T::try_from_val(env, &raw.to_val()).ok()
+}
+
+/// The version 1 `DataKey::Treasury` unit variant.
+fn legacy_treasury_key(env: &Env) -> Vec<Symbol> {
+    vec![env, Symbol::new(env, "Treasury")]
+}
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Replace the contract code with an uploaded wasm (admin only).
+    /// Run `migrate` afterwards if the new code bumps the schema version.
+    pub fn upgrade(env: Env, admin: Address, new_wasm_hash: BytesN<32>) -> Result<(), Error> {
+        admin.require_auth();
+        storage::extend_instance(&env);
+        require_admin(&env, &admin)?;
+
+        env.deployer()
+            .update_current_contract_wasm(new_wasm_hash.clone());
+
+        ContractUpgraded {
+            admin,
+            wasm_hash: new_wasm_hash,
+        }
+        .publish(&env);
+
+        Ok(())
+    }
+
+    /// Bring contract-wide storage to the current schema version (admin
+    /// only). `token` is the token version 1 markets were created with, as
+    /// they did not record it. Returns the new version; calling it again is
+    /// a no-op.
+    pub fn migrate(env: Env, admin: Address, token: Address) -> Result<u32, Error> {
+        admin.require_auth();
+        storage::extend_instance(&env);
+        require_admin(&env, &admin)?;
+
+        let from_version = storage::get_schema_version(&env);
+        if from_version >= SCHEMA_VERSION {
+            return Ok(from_version);
+        }
+
+        if from_version < 2 {
+            // Credit the untyped treasury to the token it was collected in
+            let key = legacy_treasury_key(&env);
+            let balance: Option<i128> = env.storage().instance().get(&key);
+            if let Some(balance) = balance {
+                add_to_treasury(&env, &token, balance);
+                env.storage().instance().remove(&key);
+            }
+            if !env.storage().instance().has(&DataKey::Config) {
+                storage::set_config(&env, &Default::default());
+            }
+            env.storage().instance().set(&DataKey::LegacyToken, &token);
+        }
+
+        if from_version < 5 {
+            // Rewrite the config with defaults for the parameters added since
+            storage::set_config(&env, &storage::get_config(&env));
+        }
+
+        storage::set_schema_version(&env, SCHEMA_VERSION);
+
+        Migrated {
+            admin,
+            from_version,
+            to_version: SCHEMA_VERSION,
+        }
+        .publish(&env);
+
+        Ok(SCHEMA_VERSION)
+    }
+
+    /// Move a prediction and the given users' stakes from instance storage
+    /// (used by earlier versions of this contract) to persistent storage,
+    /// converting version 1 predictions to the current layout. A market's
+    /// stakes can be moved over several calls. Once every winner is found to
+    /// have claimed, the rounding dust goes to the treasury as after a final
+    /// claim. Returns the number of stakes moved. Admin only.
+    pub fn migrate_prediction(
+        env: Env,
+        admin: Address,
+        prediction_id: u64,
+        users: Vec<Address>,
+    ) -> Result<u32, Error> {
+        admin.require_auth();
+        storage::extend_instance(&env);
+        require_admin(&env, &admin)?;
+
+        let key = DataKey::Prediction(prediction_id);
+        let legacy: Option<Map<Symbol, Val>> = env.storage().instance().get(&key);
+        let mut prediction = match legacy {
+            Some(raw) if is_legacy_prediction(&env, &raw) => {
+                let token = env
+                    .storage()
+                    .instance()
+                    .get(&DataKey::LegacyToken)
+                    .ok_or(Error::MigrationRequired)?;
+                let old = LegacyPrediction::try_from_val(&env, &raw.to_val())
+                    .map_err(|_| Error::PredictionNotFound)?;
+                old.into_current(&env, token)
+            }
+            Some(raw) => decode_prediction(&env, raw).ok_or(Error::PredictionNotFound)?,
+            None => {
+                storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?
+            }
+        };
+
+        // Only version 1 wrote stakes to instance storage, so each one found
+        // there is converted, even if its prediction was moved by an earlier
+        // call
+        let mut moved = 0;
+        for user in users.iter() {
+            let key = DataKey::UserStake(prediction_id, user.clone());
+            let legacy: Option<Stake> = env.storage().instance().get(&key);
+            if let Some(stake) = legacy {
+                // Version 1 had no position index
+                storage::add_user_position(&env, &user, prediction_id, MarketKind::Binary);
+
+                // Count winners who already claimed under version 1, sweeping
+                // the dust as a final claim would once all of them have
+                if stake.claimed {
+                    let payout = prediction.settlement.payout(stake.amount);
+                    prediction.settlement.record_claim(
+                        &env,
+                        &prediction.token,
+                        stake.amount,
+                        payout,
+                    );
+                }
+                storage::set_stake(&env, prediction_id, &stake);
+                moved += 1;
+            }
+        }
+        storage::set_prediction(&env, &prediction);
+
+        Ok(moved)
+    }
+
+    /// Get the schema version of the stored data
+    pub fn get_schema_version(env: Env) -> u32 {
+        storage::get_schema_version(&env)
+    }
+}
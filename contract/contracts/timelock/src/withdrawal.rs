@@ -0,0 +1,72 @@
+//! Early exit from binary predictions.
+//!
+//! While a prediction is still taking stakes, a staker can take their stake
+//! back out minus `Config::exit_penalty_bps`. Part of the penalty goes to the
+//! treasury and the rest stays in the market as `forfeited`, which is added
+//! to the pool the winners share. A market that ends without winners
+//! (invalid, cancelled or expired) releases it to the treasury instead.
+
+use soroban_sdk::{contractimpl, token, Address, Env};
+
+use crate::{
+    add_to_treasury, record_increment, require_not_paused, storage, Error, Operation,
+    PredictionStatus, StakeWithdrawn, TimeLockContract, TimeLockContractArgs,
+    TimeLockContractClient,
+};
+
+#[contractimpl]
+impl TimeLockContract {
+    /// Withdraw a stake from a prediction before staking closes, paying the
+    /// exit penalty. Returns the amount sent back to `user`.
+    pub fn withdraw_stake(env: Env, prediction_id: u64, user: Address) -> Result<i128, Error> {
+        user.require_auth();
+        storage::extend_instance(&env);
+        require_not_paused(&env, Operation::Stake)?;
+
+        let mut prediction =
+            storage::get_prediction(&env, prediction_id).ok_or(Error::PredictionNotFound)?;
+        if prediction.status != PredictionStatus::Open {
+            return Err(Error::PredictionNotOpen);
+        }
+        if env.ledger().timestamp() >= prediction.close_time {
+            return Err(Error::PredictionExpired);
+        }
+
+        let stake = storage::get_stake(&env, prediction_id, &user).ok_or(Error::StakeNotFound)?;
+        if stake.claimed {
+            return Err(Error::AlreadyClaimed);
+        }
+
+        let (penalty, to_treasury) = storage::get_config(&env).exit_penalty(stake.amount);
+        let amount = stake.amount - penalty;
+
+        if stake.choice {
+            prediction.yes_pool -= stake.amount;
+        } else {
+            prediction.no_pool -= stake.amount;
+        }
+        prediction.forfeited += penalty - to_treasury;
+        add_to_treasury(&env, &prediction.token, to_treasury);
+        storage::set_prediction(&env, &prediction);
+
+        // The history keeps the withdrawal, so staking again does not
+        // index the market twice
+        storage::remove_stake(&env, prediction_id, &user);
+        record_increment(&env, prediction_id, &user, -stake.amount)?;
+
+        let token_client = token::Client::new(&env, &prediction.token);
+        token_client.transfer(&env.current_contract_address(), &user, &amount);
+
+        StakeWithdrawn {
+            prediction_id,
+            user,
+            amount,
+            penalty,
+            yes_pool: prediction.yes_pool,
+            no_pool: prediction.no_pool,
+        }
+        .publish(&env);
+
+        Ok(amount)
+    }
+}
@@ -11,8 +11,9 @@ class BlockchainIntegration {
         this.server = new StellarSdk.SorobanRpc.Server(this.rpcUrl);
     }
 
-    // Create prediction directly from browser
-    async createPrediction(question, unlockTime, initialChoice) {
+    // Create prediction directly from browser. Staking closes at unlockTime;
+    // resolveAfter (the earliest resolution) defaults to the same time.
+    async createPrediction(question, unlockTime, initialChoice, resolveAfter = unlockTime) {
         try {
             console.log('🚀 Creating prediction on blockchain...');
             
@@ -35,7 +36,8 @@ class BlockchainIntegration {
             const params = [
                 new StellarSdk.Address(publicKey).toScVal(), // creator
                 StellarSdk.nativeToScVal(question, { type: 'string' }), // question
-                StellarSdk.nativeToScVal(unlockTime, { type: 'u64' }), // unlock_time
+                StellarSdk.nativeToScVal(unlockTime, { type: 'u64' }), // close_time
+                StellarSdk.nativeToScVal(resolveAfter, { type: 'u64' }), // resolve_after
                 StellarSdk.nativeToScVal(initialChoice, { type: 'bool' }), // initial_choice
                 new StellarSdk.Address(this.nativeTokenId).toScVal() // token
             ];